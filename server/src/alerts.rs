@@ -0,0 +1,145 @@
+use crate::config::Config;
+use crate::data::{Forecast, GetForecast, Project};
+use crate::distribution::MILLIS_PER_HOUR;
+use crate::forecast;
+use crate::sqldata;
+use chrono::TimeZone;
+use lettre::{Message, SendmailTransport, Transport};
+use log::{error, info};
+use rusqlite::Connection;
+
+fn hours(millis: i64) -> String {
+  format!("{:.2}", millis as f64 / MILLIS_PER_HOUR as f64)
+}
+
+fn alert_text(config: &Config, project: &Project, threshold: i64, forecast: &Forecast) -> String {
+  let exhaustion = match forecast.exhaustiondate {
+    Some(d) => chrono::Utc
+      .timestamp_millis_opt(d)
+      .single()
+      .map(|d| d.format("%Y-%m-%d").to_string())
+      .unwrap_or_default(),
+    None => "unknown".to_string(),
+  };
+  format!(
+    "Project '{}' is low on allocated hours.\n\n\
+     remaining: {} hours\n\
+     alert threshold: {} hours\n\
+     burn rate: {} hours per week, over the last {} weeks\n\
+     runs out: {}\n\n\
+     {}\n",
+    project.name,
+    hours(forecast.remaining),
+    hours(threshold),
+    hours(forecast.burnrate),
+    forecast.weeks,
+    exhaustion,
+    config.orgauth_config.mainsite,
+  )
+}
+
+// email one project admin, from the orgauth email domain.
+fn send_alert(
+  config: &Config,
+  to: &str,
+  project: &Project,
+  threshold: i64,
+  forecast: &Forecast,
+) -> Result<(), orgauth::error::Error> {
+  let oc = &config.orgauth_config;
+  let from = format!("{} <no-reply@{}>", oc.appname, oc.emaildomain);
+  let subject = format!(
+    "{}: '{}' is low on allocated hours",
+    oc.appname, project.name
+  );
+  let email = Message::builder()
+    .from(
+      from
+        .parse()
+        .map_err(|e: lettre::address::AddressError| e.to_string())?,
+    )
+    .to(
+      to.parse()
+        .map_err(|e: lettre::address::AddressError| e.to_string())?,
+    )
+    .subject(subject.as_str())
+    .body(alert_text(config, project, threshold, forecast))
+    .map_err(|e| e.to_string())?;
+  SendmailTransport::new()
+    .send(&email)
+    .map_err(|e| e.to_string())?;
+  Ok(())
+}
+
+// alert projects whose remaining allocation has dropped to their threshold.
+// one alert per admin per crossing; alerts reset when remaining goes back
+// above the threshold, for instance after a new allocation.
+pub fn check_alerts(config: &Config) -> Result<(), orgauth::error::Error> {
+  let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+  for project in sqldata::alert_projects(&conn)? {
+    // one project's trouble doesn't hold up the rest.
+    if let Err(e) = check_project_alert(config, &conn, &project) {
+      error!("alert error, project {}: {}", project.id, e);
+    }
+  }
+  Ok(())
+}
+
+// the project's forecast, if remaining allocation is at or below its
+// threshold.
+pub fn low_allocation(
+  conn: &Connection,
+  project: &Project,
+) -> Result<Option<Forecast>, orgauth::error::Error> {
+  let threshold = match project.alert_threshold {
+    Some(t) => t,
+    None => return Ok(None),
+  };
+  let forecast = forecast::project_forecast(
+    conn,
+    GetForecast {
+      project: project.id,
+      weeks: None,
+    },
+  )?;
+  if forecast.remaining <= threshold {
+    Ok(Some(forecast))
+  } else {
+    Ok(None)
+  }
+}
+
+fn check_project_alert(
+  config: &Config,
+  conn: &Connection,
+  project: &Project,
+) -> Result<(), orgauth::error::Error> {
+  let threshold = match project.alert_threshold {
+    Some(t) => t,
+    None => return Ok(()),
+  };
+  if let Some(forecast) = low_allocation(conn, project)? {
+    for (user, email, sent) in sqldata::alert_recipients(conn, project.id)? {
+      if sent {
+        continue;
+      }
+      // if sending fails, try again next time.
+      match send_alert(config, email.as_str(), project, threshold, &forecast) {
+        Ok(()) => {
+          info!(
+            "sent low allocation alert for project {} to user {}",
+            project.id, user
+          );
+          sqldata::set_alert_sent(conn, project.id, user)?;
+        }
+        Err(e) => error!(
+          "alert email error, project {}, user {}: {:?}",
+          project.id, user, e
+        ),
+      }
+    }
+  } else {
+    sqldata::clear_alerts_sent(conn, project.id)?;
+  }
+  Ok(())
+}
@@ -0,0 +1,294 @@
+use crate::config::Config;
+use crate::data::{
+  CalcDistribution, ClockIn, ClockOut, CsvKind, ExportCsv, GetForecast, ImportCsv,
+};
+use crate::interfaces;
+use crate::messages::{ServerResponse, UserMessage};
+use crate::sqldata;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use log::{error, info};
+use serde_derive::Deserialize;
+use std::error::Error;
+use std::str::FromStr;
+
+// json api for scripts and tools.  requests carry a personal api token as
+// 'Authorization: Bearer <token>', and are handled as the equivalent private
+// message, with the same permission checks.
+
+#[derive(Deserialize, Debug)]
+pub struct DistributionQuery {
+  pub hours: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ForecastQuery {
+  pub weeks: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApiClockIn {
+  pub description: Option<String>,
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+  parse_bearer(req.headers().get(header::AUTHORIZATION)?.to_str().ok()?)
+}
+
+// the token from 'Bearer <token>'.  the scheme is case insensitive.
+fn parse_bearer(value: &str) -> Option<&str> {
+  let (scheme, token) = value.trim().split_once(|c: char| c.is_ascii_whitespace())?;
+  let token = token.trim();
+  if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() {
+    Some(token)
+  } else {
+    None
+  }
+}
+
+fn api_user(config: &Config, req: &HttpRequest) -> Result<Option<i64>, orgauth::error::Error> {
+  match bearer_token(req) {
+    Some(token) => {
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      sqldata::api_token_user(&conn, token)
+    }
+    None => Ok(None),
+  }
+}
+
+fn api_message(
+  config: &Config,
+  req: &HttpRequest,
+  msg: UserMessage,
+) -> Result<Option<ServerResponse>, Box<dyn Error>> {
+  match api_user(config, req)? {
+    Some(uid) => Ok(Some(interfaces::timeclonk_interface_loggedin(
+      config, uid, &msg,
+    )?)),
+    None => Ok(None),
+  }
+}
+
+fn api_response(
+  config: &Config,
+  req: &HttpRequest,
+  what: &str,
+  data: Option<serde_json::Value>,
+) -> HttpResponse {
+  info!(
+    "api msg: {}  \n connection_info: {:?}",
+    what,
+    req.connection_info()
+  );
+  let msg = UserMessage {
+    what: what.to_string(),
+    data: data,
+  };
+  match api_message(config, req, msg) {
+    Ok(Some(sr)) => {
+      if sr.what.ends_with("_denied") {
+        HttpResponse::Forbidden().json(sr)
+      } else if sr.what.ends_with("_locked") || sr.what.ends_with("_conflict") {
+        HttpResponse::Conflict().json(sr)
+      } else if sr.what.ends_with("_errors") || sr.what.ends_with("_failed") {
+        HttpResponse::UnprocessableEntity().json(sr)
+      } else if sr.what == "csv" {
+        HttpResponse::Ok()
+          .content_type("text/csv; charset=utf-8")
+          .body(sr.content.as_str().unwrap_or("").to_string())
+      } else {
+        HttpResponse::Ok().json(sr)
+      }
+    }
+    Ok(None) => HttpResponse::Unauthorized().json(ServerResponse {
+      what: "invalid token".to_string(),
+      content: serde_json::Value::Null,
+    }),
+    Err(e) => {
+      error!("'api' err: {:?}", e);
+      HttpResponse::InternalServerError().json(ServerResponse {
+        what: "server error".to_string(),
+        content: serde_json::Value::String(e.to_string()),
+      })
+    }
+  }
+}
+
+fn to_value<T: serde::Serialize>(t: T) -> Option<serde_json::Value> {
+  serde_json::to_value(t).ok()
+}
+
+// GET /api/v1/projects
+pub async fn projects(config: web::Data<Config>, req: HttpRequest) -> HttpResponse {
+  api_response(&config, &req, "GetProjectList", None)
+}
+
+// GET /api/v1/projects/{id}
+pub async fn project(
+  config: web::Data<Config>,
+  path: web::Path<i64>,
+  req: HttpRequest,
+) -> HttpResponse {
+  api_response(&config, &req, "GetProjectEdit", to_value(*path))
+}
+
+// GET /api/v1/projects/{id}/time
+pub async fn project_time(
+  config: web::Data<Config>,
+  path: web::Path<i64>,
+  req: HttpRequest,
+) -> HttpResponse {
+  api_response(&config, &req, "GetProjectTime", to_value(*path))
+}
+
+// POST /api/v1/projects/{id}/time, with a SaveProjectTime body.  the project
+// comes from the path.
+pub async fn save_project_time(
+  config: web::Data<Config>,
+  path: web::Path<i64>,
+  item: web::Json<serde_json::Value>,
+  req: HttpRequest,
+) -> HttpResponse {
+  let mut spt = item.into_inner();
+  if let Some(o) = spt.as_object_mut() {
+    o.insert("project".to_string(), serde_json::Value::from(*path));
+  }
+  api_response(&config, &req, "SaveProjectTime", Some(spt))
+}
+
+// GET /api/v1/projects/{id}/distribution?hours=N
+pub async fn distribution(
+  config: web::Data<Config>,
+  path: web::Path<i64>,
+  query: web::Query<DistributionQuery>,
+  req: HttpRequest,
+) -> HttpResponse {
+  let cd = CalcDistribution {
+    project: *path,
+    hours: query.hours,
+    strategy: None,
+  };
+  api_response(&config, &req, "CalcDistribution", to_value(cd))
+}
+
+// GET /api/v1/projects/{id}/forecast?weeks=N
+pub async fn forecast(
+  config: web::Data<Config>,
+  path: web::Path<i64>,
+  query: web::Query<ForecastQuery>,
+  req: HttpRequest,
+) -> HttpResponse {
+  let gf = GetForecast {
+    project: *path,
+    weeks: query.weeks,
+  };
+  api_response(&config, &req, "GetForecast", to_value(gf))
+}
+
+// GET /api/v1/projects/{id}/csv/{kind}, where kind is time, pay or
+// allocations.
+pub async fn export_csv(
+  config: web::Data<Config>,
+  path: web::Path<(i64, String)>,
+  req: HttpRequest,
+) -> HttpResponse {
+  let (project, kind) = path.into_inner();
+  match CsvKind::from_str(kind.as_str()) {
+    Ok(kind) => api_response(
+      &config,
+      &req,
+      "ExportCsv",
+      to_value(ExportCsv {
+        project: project,
+        kind: kind,
+      }),
+    ),
+    Err(_) => HttpResponse::NotFound().finish(),
+  }
+}
+
+// POST /api/v1/projects/{id}/csv/{kind}, with a csv body.
+pub async fn import_csv(
+  config: web::Data<Config>,
+  path: web::Path<(i64, String)>,
+  body: String,
+  req: HttpRequest,
+) -> HttpResponse {
+  let (project, kind) = path.into_inner();
+  match CsvKind::from_str(kind.as_str()) {
+    Ok(kind) => api_response(
+      &config,
+      &req,
+      "ImportCsv",
+      to_value(ImportCsv {
+        project: project,
+        kind: kind,
+        csv: body,
+      }),
+    ),
+    Err(_) => HttpResponse::NotFound().finish(),
+  }
+}
+
+// GET /api/v1/projects/{id}/invoices
+pub async fn invoices(
+  config: web::Data<Config>,
+  path: web::Path<i64>,
+  req: HttpRequest,
+) -> HttpResponse {
+  api_response(&config, &req, "ListInvoices", to_value(*path))
+}
+
+// POST /api/v1/projects/{id}/clockin, with an optional description.
+pub async fn clock_in(
+  config: web::Data<Config>,
+  path: web::Path<i64>,
+  item: Option<web::Json<ApiClockIn>>,
+  req: HttpRequest,
+) -> HttpResponse {
+  let ci = ClockIn {
+    project: *path,
+    description: item
+      .and_then(|i| i.into_inner().description)
+      .unwrap_or("".to_string()),
+  };
+  api_response(&config, &req, "ClockIn", to_value(ci))
+}
+
+// POST /api/v1/clockout, with an optional description.
+pub async fn clock_out(
+  config: web::Data<Config>,
+  item: Option<web::Json<ClockOut>>,
+  req: HttpRequest,
+) -> HttpResponse {
+  api_response(
+    &config,
+    &req,
+    "ClockOut",
+    item.and_then(|i| to_value(i.into_inner())),
+  )
+}
+
+// GET /api/v1/timers
+pub async fn timers(config: web::Data<Config>, req: HttpRequest) -> HttpResponse {
+  api_response(&config, &req, "GetActiveTimers", None)
+}
+
+// GET /api/v1/time
+pub async fn user_time(config: web::Data<Config>, req: HttpRequest) -> HttpResponse {
+  api_response(&config, &req, "GetUserTime", None)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn bearer_schemes() {
+    assert_eq!(parse_bearer("Bearer abc"), Some("abc"));
+    assert_eq!(parse_bearer("bearer abc"), Some("abc"));
+    assert_eq!(parse_bearer("  BEARER   abc  "), Some("abc"));
+    assert_eq!(parse_bearer("Bearer "), None);
+    assert_eq!(parse_bearer("Basic abc"), None);
+    assert_eq!(parse_bearer("Bearerabc"), None);
+  }
+}
@@ -7,5 +7,7 @@ pub struct Config {
   pub ip: String,
   pub port: u16,
   pub static_path: Option<PathBuf>,
+  // deleted time entries and allocations are purged after this many days.
+  pub purge_deleted_days: Option<i64>,
   pub orgauth_config: orgauth_data::Config,
 }
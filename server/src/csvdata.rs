@@ -0,0 +1,377 @@
+use crate::data::{
+  CsvKind, CsvRowError, PayType, SaveAllocation, SavePayEntry, SaveProjectTime, SaveTimeEntry,
+};
+use crate::distribution::MILLIS_PER_HOUR;
+use crate::sqldata;
+use chrono::TimeZone;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+// csv export and import of a project's time entries, pay entries and
+// allocations.  dates are UTC, durations are decimal hours, and users are
+// given by name.  rows with an id update that entry; rows without are new.
+
+const TIME_HEADERS: [&str; 7] = [
+  "id",
+  "user",
+  "task",
+  "ignore",
+  "startdate",
+  "enddate",
+  "duration",
+];
+const PAY_HEADERS: [&str; 6] = ["id", "user", "type", "description", "date", "hours"];
+const ALLOCATION_HEADERS: [&str; 4] = ["id", "description", "date", "hours"];
+
+fn csv_date(millis: i64) -> String {
+  chrono::Utc
+    .timestamp_millis_opt(millis)
+    .single()
+    .map(|d| d.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+    .unwrap_or_default()
+}
+
+// two places if that reads back the same, otherwise as many as it takes, so
+// importing an export changes nothing.
+fn csv_hours(millis: i64) -> String {
+  let hours = millis as f64 / MILLIS_PER_HOUR as f64;
+  let short = format!("{:.2}", hours);
+  if parse_hours(short.as_str()) == Ok(millis) {
+    short
+  } else {
+    format!("{}", hours)
+  }
+}
+
+fn parse_date(s: &str) -> Result<i64, String> {
+  if let Ok(d) = chrono::DateTime::parse_from_rfc3339(s) {
+    return Ok(d.timestamp_millis());
+  }
+  for fmt in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"].iter() {
+    if let Ok(d) = chrono::NaiveDateTime::parse_from_str(s, fmt) {
+      return Ok(chrono::Utc.from_utc_datetime(&d).timestamp_millis());
+    }
+  }
+  match chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+    Ok(d) => Ok(
+      chrono::Utc
+        .from_utc_datetime(&d.and_hms_opt(0, 0, 0).unwrap_or_default())
+        .timestamp_millis(),
+    ),
+    Err(_) => Err(format!("invalid date: '{}'", s)),
+  }
+}
+
+fn parse_hours(s: &str) -> Result<i64, String> {
+  match s.parse::<f64>() {
+    Ok(h) if h.is_finite() => Ok((h * MILLIS_PER_HOUR as f64).round() as i64),
+    _ => Err(format!("invalid hours: '{}'", s)),
+  }
+}
+
+fn parse_id(s: &str, ids: &[i64]) -> Result<Option<i64>, String> {
+  if s.is_empty() {
+    Ok(None)
+  } else {
+    match s.parse::<i64>() {
+      Ok(id) if ids.contains(&id) => Ok(Some(id)),
+      _ => Err(format!("id '{}' is not in this project", s)),
+    }
+  }
+}
+
+fn write_csv(headers: &[&str], rows: Vec<Vec<String>>) -> Result<String, csv::Error> {
+  let mut wtr = csv::Writer::from_writer(Vec::new());
+  wtr.write_record(headers)?;
+  for row in rows {
+    wtr.write_record(&row)?;
+  }
+  let bytes = wtr.into_inner().map_err(|e| e.into_error())?;
+  Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+pub fn export_csv(
+  conn: &Connection,
+  project: i64,
+  kind: CsvKind,
+) -> Result<String, orgauth::error::Error> {
+  let usernames: HashMap<i64, String> = sqldata::user_list(conn)?
+    .into_iter()
+    .map(|u| (u.id, u.name))
+    .collect();
+  let username = |id: &i64| usernames.get(id).cloned().unwrap_or_default();
+
+  let res = match kind {
+    CsvKind::Time => write_csv(
+      &TIME_HEADERS,
+      sqldata::time_entries(conn, project)?
+        .into_iter()
+        .map(|te| {
+          vec![
+            te.id.to_string(),
+            username(&te.user),
+            te.description,
+            if te.ignore { "ignore" } else { "" }.to_string(),
+            csv_date(te.startdate),
+            csv_date(te.enddate),
+            csv_hours(te.enddate - te.startdate),
+          ]
+        })
+        .collect(),
+    ),
+    CsvKind::Pay => write_csv(
+      &PAY_HEADERS,
+      sqldata::pay_entries(conn, project)?
+        .into_iter()
+        .map(|pe| {
+          vec![
+            pe.id.to_string(),
+            username(&pe.user),
+            match pe.paytype {
+              PayType::Invoiced => "invoiced",
+              PayType::Paid => "paid",
+            }
+            .to_string(),
+            pe.description,
+            csv_date(pe.paymentdate),
+            csv_hours(pe.duration),
+          ]
+        })
+        .collect(),
+    ),
+    CsvKind::Allocations => write_csv(
+      &ALLOCATION_HEADERS,
+      sqldata::allocations(conn, project)?
+        .into_iter()
+        .map(|a| {
+          vec![
+            a.id.to_string(),
+            a.description,
+            csv_date(a.allocationdate),
+            csv_hours(a.duration),
+          ]
+        })
+        .collect(),
+    ),
+  };
+
+  res.map_err(|e| orgauth::error::Error::String(e.to_string()))
+}
+
+// a csv row, with fields looked up by header name.
+struct CsvRow<'a> {
+  headers: &'a HashMap<String, usize>,
+  record: csv::StringRecord,
+}
+
+impl<'a> CsvRow<'a> {
+  fn get(&self, name: &str) -> &str {
+    self
+      .headers
+      .get(name)
+      .and_then(|i| self.record.get(*i))
+      .unwrap_or("")
+      .trim()
+  }
+}
+
+fn member(members: &HashMap<String, i64>, name: &str) -> Result<i64, String> {
+  match members.get(&name.to_lowercase()) {
+    Some(id) => Ok(*id),
+    None => Err(format!("'{}' is not a project member", name)),
+  }
+}
+
+// add a row's entry to 'spt'.
+fn import_row(
+  row: &CsvRow,
+  kind: CsvKind,
+  project: i64,
+  ids: &[i64],
+  members: &HashMap<String, i64>,
+  spt: &mut SaveProjectTime,
+) -> Result<(), String> {
+  let id = parse_id(row.get("id"), ids)?;
+  match kind {
+    CsvKind::Time => {
+      let startdate = parse_date(row.get("startdate"))?;
+      let enddate = match (row.get("enddate"), row.get("duration")) {
+        ("", "") => return Err("enddate or duration is required".to_string()),
+        ("", d) => startdate + parse_hours(d)?,
+        (e, _) => parse_date(e)?,
+      };
+      if enddate < startdate {
+        return Err("enddate is before startdate".to_string());
+      }
+      spt.savetimeentries.push(SaveTimeEntry {
+        id: id,
+        project: project,
+        user: member(members, row.get("user"))?,
+        description: row.get("task").to_string(),
+        startdate: startdate,
+        enddate: enddate,
+        ignore: !row.get("ignore").is_empty(),
+        tags: None,
+        task: None,
+        clear_task: false,
+        changeddate: None,
+      });
+    }
+    CsvKind::Pay => {
+      let paytype = match row.get("type").to_lowercase().as_str() {
+        "paid" => PayType::Paid,
+        "invoiced" => PayType::Invoiced,
+        t => return Err(format!("type '{}' should be paid or invoiced", t)),
+      };
+      spt.savepayentries.push(SavePayEntry {
+        id: id,
+        project: project,
+        user: member(members, row.get("user"))?,
+        duration: parse_hours(row.get("hours"))?,
+        paytype: paytype,
+        paymentdate: parse_date(row.get("date"))?,
+        description: row.get("description").to_string(),
+        changeddate: None,
+      });
+    }
+    CsvKind::Allocations => {
+      spt.saveallocations.push(SaveAllocation {
+        id: id,
+        project: project,
+        duration: parse_hours(row.get("hours"))?,
+        allocationdate: parse_date(row.get("date"))?,
+        description: row.get("description").to_string(),
+        changeddate: None,
+      });
+    }
+  }
+  Ok(())
+}
+
+// parse a csv file into changes for save_project_time.  any bad row fails
+// the import, with an error for each bad row.
+pub fn import_csv(
+  conn: &Connection,
+  project: i64,
+  kind: CsvKind,
+  csv: &str,
+) -> Result<Result<SaveProjectTime, Vec<CsvRowError>>, orgauth::error::Error> {
+  let mut spt = SaveProjectTime {
+    project: project,
+    savetimeentries: Vec::new(),
+    deletetimeentries: Vec::new(),
+    savepayentries: Vec::new(),
+    deletepayentries: Vec::new(),
+    saveallocations: Vec::new(),
+    deleteallocations: Vec::new(),
+    unlock: None,
+  };
+  let mut errors = Vec::new();
+
+  let mut rdr = csv::ReaderBuilder::new()
+    .flexible(true)
+    .from_reader(csv.as_bytes());
+  let headers: HashMap<String, usize> = match rdr.headers() {
+    Ok(h) => h
+      .iter()
+      .enumerate()
+      .map(|(i, h)| (h.trim().to_lowercase(), i))
+      .collect(),
+    Err(e) => {
+      return Ok(Err(vec![CsvRowError {
+        row: 1,
+        error: e.to_string(),
+      }]))
+    }
+  };
+
+  let required: &[&str] = match kind {
+    CsvKind::Time => &["user", "task", "startdate"],
+    CsvKind::Pay => &["user", "type", "date", "hours"],
+    CsvKind::Allocations => &["description", "date", "hours"],
+  };
+  let missing: Vec<&str> = required
+    .iter()
+    .filter(|r| !headers.contains_key(**r))
+    .copied()
+    .collect();
+  if !missing.is_empty() {
+    return Ok(Err(vec![CsvRowError {
+      row: 1,
+      error: format!("missing columns: {}", missing.join(", ")),
+    }]));
+  }
+
+  let members: HashMap<String, i64> = sqldata::member_list(conn, project)?
+    .into_iter()
+    .map(|m| (m.name.to_lowercase(), m.id))
+    .collect();
+  let ids: Vec<i64> = match kind {
+    CsvKind::Time => sqldata::time_entries(conn, project)?
+      .iter()
+      .map(|te| te.id)
+      .collect(),
+    CsvKind::Pay => sqldata::pay_entries(conn, project)?
+      .iter()
+      .map(|pe| pe.id)
+      .collect(),
+    CsvKind::Allocations => sqldata::allocations(conn, project)?
+      .iter()
+      .map(|a| a.id)
+      .collect(),
+  };
+
+  for (i, record) in rdr.records().enumerate() {
+    let rownum = i + 2;
+    let row = match record {
+      Ok(record) => CsvRow {
+        headers: &headers,
+        record: record,
+      },
+      Err(e) => {
+        errors.push(CsvRowError {
+          row: rownum,
+          error: e.to_string(),
+        });
+        continue;
+      }
+    };
+
+    let res = import_row(&row, kind, project, &ids, &members, &mut spt);
+
+    if let Err(e) = res {
+      errors.push(CsvRowError {
+        row: rownum,
+        error: e,
+      });
+    }
+  }
+
+  if errors.is_empty() {
+    Ok(Ok(spt))
+  } else {
+    Ok(Err(errors))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn hours_round_trip() {
+    assert_eq!(csv_hours(2 * MILLIS_PER_HOUR), "2.00");
+    assert_eq!(csv_hours(MILLIS_PER_HOUR / 4), "0.25");
+    for millis in vec![20 * 60 * 1000, 1, MILLIS_PER_HOUR + 7, -20 * 60 * 1000] {
+      assert_eq!(parse_hours(csv_hours(millis).as_str()), Ok(millis));
+    }
+  }
+
+  #[test]
+  fn dates_round_trip() {
+    assert_eq!(csv_date(1704099600000), "2024-01-01T09:00:00Z");
+    for millis in vec![1704099600000, 1704099600123] {
+      assert_eq!(parse_date(csv_date(millis).as_str()), Ok(millis));
+    }
+  }
+}
@@ -51,6 +51,12 @@ pub struct SaveProject {
   pub public: bool,
   pub rate: Option<i64>,
   pub currency: Option<String>,
+  // none leaves the current strategy unchanged.
+  pub distribution: Option<DistributionStrategy>,
+  // the changeddate the client last saw.  saving over a newer change is a
+  // conflict; none skips the check.
+  #[serde(default)]
+  pub changeddate: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -79,11 +85,35 @@ impl FromStr for Role {
   }
 }
 
+// how hours are split between members when paying out.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum DistributionStrategy {
+  // earliest unpaid work gets paid first.
+  WorkedFirst,
+  // in proportion to each member's unpaid hours.
+  ProRata,
+  // evenly between members with unpaid hours.
+  EqualSplit,
+  // worked first, but no member gets more than this many hours.
+  Capped(f64),
+}
+
+impl Default for DistributionStrategy {
+  fn default() -> Self {
+    DistributionStrategy::WorkedFirst
+  }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct SaveProjectMember {
   pub id: i64,
   pub delete: bool,
   pub role: Role,
+  // none leaves the member's rate as it is.
+  pub rate: Option<f64>,
+  // back to the project rate.
+  #[serde(default)]
+  pub clear_rate: bool,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -119,6 +149,14 @@ pub struct Project {
   pub public: bool,
   pub rate: Option<f64>,
   pub currency: Option<String>,
+  pub distribution: DistributionStrategy,
+  // time entries starting before this date can't be changed.
+  pub lockdate: Option<i64>,
+  // admins are emailed when remaining allocated millis drop to this.
+  pub alert_threshold: Option<i64>,
+  // overlapping time entries are errors, not warnings.
+  #[serde(default)]
+  pub reject_overlaps: bool,
   pub createdate: i64,
   pub changeddate: i64,
 }
@@ -128,6 +166,7 @@ pub struct ProjectMember {
   pub id: i64,
   pub name: String,
   pub role: Role,
+  pub rate: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -136,6 +175,28 @@ pub struct User {
   pub name: String,
 }
 
+// a rate change, effective from 'effectivedate'.  with no user, it's the
+// project rate.  a member rate of none means the member goes back to the
+// project rate.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectRate {
+  pub id: i64,
+  pub project: i64,
+  pub user: Option<i64>,
+  pub rate: Option<f64>,
+  pub effectivedate: i64,
+  pub createdate: i64,
+  pub creator: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetProjectRate {
+  pub project: i64,
+  pub user: Option<i64>,
+  pub rate: Option<f64>,
+  pub effectivedate: i64,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProjectEdit {
   pub project: Project,
@@ -154,6 +215,13 @@ pub struct TimeEntry {
   pub createdate: i64,
   pub changeddate: i64,
   pub creator: i64,
+  // on an invoice that hasn't been voided.
+  #[serde(default)]
+  pub billed: bool,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub task: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -165,17 +233,75 @@ pub struct SaveTimeEntry {
   pub startdate: i64,
   pub enddate: i64,
   pub ignore: bool,
+  // project tags.  none leaves the entry's tags as they are.
+  pub tags: Option<Vec<String>>,
+  // none leaves the entry's task as it is, unless the project changes.
+  #[serde(default)]
+  pub task: Option<i64>,
+  // removes the entry from its task.
+  #[serde(default)]
+  pub clear_task: bool,
+  // as in SaveProject.
+  #[serde(default)]
+  pub changeddate: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetProjectLock {
+  pub project: i64,
+  pub lockdate: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetProjectAlert {
+  pub project: i64,
+  pub threshold: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LockedTimeEntry {
+  // none for a new entry.
+  pub id: Option<i64>,
+  pub reason: String,
 }
 
+// an id to delete, optionally with the changeddate the client last saw, as
+// in SaveProject.  plain ids skip the check.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum DeleteEntry {
+  Id(i64),
+  Seen { id: i64, changeddate: i64 },
+}
+
+impl DeleteEntry {
+  pub fn id(&self) -> i64 {
+    match self {
+      DeleteEntry::Id(id) => *id,
+      DeleteEntry::Seen { id, .. } => *id,
+    }
+  }
+  pub fn changeddate(&self) -> Option<i64> {
+    match self {
+      DeleteEntry::Id(_) => None,
+      DeleteEntry::Seen { changeddate, .. } => Some(*changeddate),
+    }
+  }
+}
+
+// every entry must be in 'project', though time entries can move from it to
+// another project the saver works on.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SaveProjectTime {
   pub project: i64,
   pub savetimeentries: Vec<SaveTimeEntry>,
-  pub deletetimeentries: Vec<i64>,
+  pub deletetimeentries: Vec<DeleteEntry>,
   pub savepayentries: Vec<SavePayEntry>,
-  pub deletepayentries: Vec<i64>,
+  pub deletepayentries: Vec<DeleteEntry>,
   pub saveallocations: Vec<SaveAllocation>,
-  pub deleteallocations: Vec<i64>,
+  pub deleteallocations: Vec<DeleteEntry>,
+  // admins can change locked time entries with this set.
+  pub unlock: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -185,6 +311,80 @@ pub struct ProjectTime {
   pub timeentries: Vec<TimeEntry>,
   pub payentries: Vec<PayEntry>,
   pub allocations: Vec<Allocation>,
+  #[serde(default)]
+  pub tasks: Vec<Task>,
+  // time entry warnings from the save that returned this.
+  #[serde(default)]
+  pub warnings: Vec<TimeEntryIssue>,
+}
+
+// bump when the export format changes.
+//  1: projects, members, time, pay entries and allocations.  tasks were
+//     added without a bump, so older version 1 files have none.
+//  2: rates, invoices and deleted entries.
+pub const EXPORT_VERSION: i64 = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Export {
+  pub version: i64,
+  pub users: Vec<User>,
+  pub projects: Vec<ProjectExport>,
+}
+
+// a version 1 project is just the ProjectTime.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectExport {
+  #[serde(flatten)]
+  pub time: ProjectTime,
+  #[serde(default)]
+  pub rates: Vec<ProjectRate>,
+  #[serde(default)]
+  pub invoices: Vec<Invoice>,
+  #[serde(default)]
+  pub invoicetimeentries: Vec<InvoiceTimeEntry>,
+  // a deleted entry's changeddate is its delete date.
+  #[serde(default)]
+  pub deletedtimeentries: Vec<TimeEntry>,
+  #[serde(default)]
+  pub deletedallocations: Vec<Allocation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvoiceTimeEntry {
+  pub invoice: i64,
+  pub timeentry: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportReport {
+  // users registered by --create_users.
+  pub newusers: Vec<String>,
+  // new projects.
+  pub projects: i64,
+  // projects already in the db, imported into.
+  pub existingprojects: i64,
+  pub timeentries: i64,
+  pub payentries: i64,
+  pub allocations: i64,
+  pub rates: i64,
+  pub invoices: i64,
+  // deleted time entries and allocations.
+  pub deleted: i64,
+  // rows already present in the db.
+  pub skipped: i64,
+  // rows that clash with a different row in the db, and weren't imported.
+  pub conflicts: Vec<ImportConflict>,
+  // time entries that fail check_time_entry, and weren't imported.
+  pub invalid: Vec<ImportConflict>,
+}
+
+// 'date' is the startdate, paymentdate or allocationdate.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportConflict {
+  pub project: String,
+  pub table: String,
+  pub user: Option<i64>,
+  pub date: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -216,6 +416,8 @@ pub struct SavePayEntry {
   pub paytype: PayType,
   pub paymentdate: i64,
   pub description: String,
+  #[serde(default)]
+  pub changeddate: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -237,6 +439,53 @@ pub struct SaveAllocation {
   pub duration: i64,
   pub allocationdate: i64,
   pub description: String,
+  #[serde(default)]
+  pub changeddate: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActiveTimer {
+  pub user: i64,
+  pub project: i64,
+  pub description: String,
+  pub startdate: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClockIn {
+  pub project: i64,
+  pub description: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClockOut {
+  // replaces the description given at clock in.
+  pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CalcDistribution {
+  pub project: i64,
+  // hours to distribute.  if none, distribute the unpaid allocation.
+  pub hours: Option<f64>,
+  // if none, use the project's strategy.
+  pub strategy: Option<DistributionStrategy>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserDistribution {
+  pub user: i64,
+  pub duration: i64,
+  // duration times the member's rate, if there is one.
+  pub amount: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Distribution {
+  pub project: i64,
+  pub strategy: DistributionStrategy,
+  pub duration: i64,
+  pub users: Vec<UserDistribution>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -249,6 +498,10 @@ pub struct PrintInvoice {
   pub due_date: Option<String>,
   pub extra_fields: Vec<ExtraField>,
   pub currency: String,
+  // if set, the invoice is recorded for this project.
+  pub project: Option<i64>,
+  // time entries billed by this invoice.
+  pub timeentries: Option<Vec<i64>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -257,3 +510,434 @@ pub struct InvoiceItem {
   pub duration: f64,
   pub rate: f64,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum InvoiceStatus {
+  Draft,
+  Sent,
+  Paid,
+  Void,
+}
+
+impl InvoiceStatus {
+  // draft -> sent -> paid.  draft and sent invoices can be voided.
+  pub fn can_change_to(&self, to: &InvoiceStatus) -> bool {
+    match (self, to) {
+      (InvoiceStatus::Draft, InvoiceStatus::Sent) => true,
+      (InvoiceStatus::Draft, InvoiceStatus::Void) => true,
+      (InvoiceStatus::Sent, InvoiceStatus::Paid) => true,
+      (InvoiceStatus::Sent, InvoiceStatus::Void) => true,
+      _ => false,
+    }
+  }
+}
+
+impl fmt::Display for InvoiceStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self)
+  }
+}
+
+impl FromStr for InvoiceStatus {
+  type Err = ();
+
+  fn from_str(input: &str) -> Result<InvoiceStatus, Self::Err> {
+    match input {
+      "Draft" => Ok(InvoiceStatus::Draft),
+      "Sent" => Ok(InvoiceStatus::Sent),
+      "Paid" => Ok(InvoiceStatus::Paid),
+      "Void" => Ok(InvoiceStatus::Void),
+      _ => Err(()),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Invoice {
+  pub id: i64,
+  pub project: i64,
+  // the invoice id printed on the invoice.
+  pub number: String,
+  pub date: String,
+  pub due_date: Option<String>,
+  pub payer: String,
+  pub payee: String,
+  pub items: Vec<InvoiceItem>,
+  pub extra_fields: Vec<ExtraField>,
+  pub total: f64,
+  pub currency: String,
+  pub status: InvoiceStatus,
+  pub createdate: i64,
+  pub changeddate: i64,
+  pub creator: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetInvoiceTime {
+  pub project: i64,
+  // by default only unbilled entries are returned.
+  pub include_billed: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InvoiceTime {
+  pub timeentries: Vec<TimeEntry>,
+  // the entries totaled per member and rate, priced by day as in
+  // distributions.
+  pub items: Vec<InvoiceItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetInvoiceStatus {
+  pub id: i64,
+  pub status: InvoiceStatus,
+}
+
+// personal api token, for 'Authorization: Bearer' requests.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiToken {
+  pub id: i64,
+  pub name: String,
+  pub createdate: i64,
+  pub lastused: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewApiToken {
+  pub name: String,
+}
+
+// the token itself is only sent back once, on creation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreatedApiToken {
+  pub apitoken: ApiToken,
+  pub token: String,
+}
+
+// calendar feed of a user's time, or of a whole project with 'project'.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IcsFeed {
+  pub id: i64,
+  pub project: Option<i64>,
+  pub createdate: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewIcsFeed {
+  pub project: Option<i64>,
+}
+
+// the feed url is only sent back once, on creation.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreatedIcsFeed {
+  pub icsfeed: IcsFeed,
+  pub url: String,
+}
+
+// an uploaded calendar file, to become time entries for 'user' on 'project'.
+// with 'keyword', only events with the keyword in their summary are used.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IcsImport {
+  pub project: i64,
+  pub user: i64,
+  pub ics: String,
+  pub keyword: Option<String>,
+}
+
+// a proposed time entry.  duplicates start when one of the user's entries does.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IcsImportEntry {
+  pub timeentry: SaveTimeEntry,
+  pub duplicate: bool,
+}
+
+// which part of a project's time a csv file holds.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CsvKind {
+  Time,
+  Pay,
+  Allocations,
+}
+
+impl FromStr for CsvKind {
+  type Err = ();
+
+  fn from_str(input: &str) -> Result<CsvKind, Self::Err> {
+    match input.to_lowercase().as_str() {
+      "time" => Ok(CsvKind::Time),
+      "pay" => Ok(CsvKind::Pay),
+      "allocations" => Ok(CsvKind::Allocations),
+      _ => Err(()),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportCsv {
+  pub project: i64,
+  pub kind: CsvKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportCsv {
+  pub project: i64,
+  pub kind: CsvKind,
+  pub csv: String,
+}
+
+// rows are numbered as in a spreadsheet, with the header as row 1.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CsvRowError {
+  pub row: usize,
+  pub error: String,
+}
+
+// hours per tag and user, as millis.  an entry with several tags counts
+// toward each; untagged entries have no tag.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagHours {
+  pub tag: Option<String>,
+  pub user: i64,
+  pub duration: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TagReport {
+  pub project: i64,
+  pub tags: Vec<TagHours>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TaskStatus {
+  Open,
+  InProgress,
+  Done,
+}
+
+impl fmt::Display for TaskStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self)
+  }
+}
+
+impl FromStr for TaskStatus {
+  type Err = ();
+
+  fn from_str(input: &str) -> Result<TaskStatus, Self::Err> {
+    match input {
+      "Open" => Ok(TaskStatus::Open),
+      "InProgress" => Ok(TaskStatus::InProgress),
+      "Done" => Ok(TaskStatus::Done),
+      _ => Err(()),
+    }
+  }
+}
+
+// a piece of work within a project.  estimate is in millis.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Task {
+  pub id: i64,
+  pub project: i64,
+  pub name: String,
+  pub estimate: Option<i64>,
+  pub status: TaskStatus,
+  pub assignee: Option<i64>,
+  pub createdate: i64,
+  pub changeddate: i64,
+  pub creator: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SaveTask {
+  pub id: Option<i64>,
+  pub project: i64,
+  pub name: String,
+  pub estimate: Option<i64>,
+  pub status: TaskStatus,
+  pub assignee: Option<i64>,
+}
+
+// logged time vs the estimate, in millis.  remaining is negative when the
+// task overran.  ignored entries don't count.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskHours {
+  pub task: Task,
+  pub logged: i64,
+  pub remaining: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskReport {
+  pub project: i64,
+  pub tasks: Vec<TaskHours>,
+  // time logged without a task.
+  pub unassigned: i64,
+}
+
+// trailing weeks for the burn rate; defaults to 4, at most 520.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetForecast {
+  pub project: i64,
+  pub weeks: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BurnPoint {
+  pub date: i64,
+  pub remaining: i64,
+}
+
+// allocated vs worked time, in millis.  burnrate is millis worked per week
+// over the last 'weeks' weeks.  exhaustiondate is when allocated time runs
+// out at that rate, or when it ran out if it already has.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Forecast {
+  pub project: i64,
+  pub allocated: i64,
+  pub worked: i64,
+  pub remaining: i64,
+  pub weeks: i64,
+  pub burnrate: i64,
+  pub exhaustiondate: Option<i64>,
+  pub burndown: Vec<BurnPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AuditAction {
+  Insert,
+  Update,
+  Delete,
+  Restore,
+  // from --import.
+  Import,
+}
+
+impl fmt::Display for AuditAction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self)
+  }
+}
+
+impl FromStr for AuditAction {
+  type Err = ();
+
+  fn from_str(input: &str) -> Result<AuditAction, Self::Err> {
+    match input {
+      "Insert" => Ok(AuditAction::Insert),
+      "Update" => Ok(AuditAction::Update),
+      "Delete" => Ok(AuditAction::Delete),
+      "Restore" => Ok(AuditAction::Restore),
+      "Import" => Ok(AuditAction::Import),
+      _ => Err(()),
+    }
+  }
+}
+
+// a change to a timeentry, payentry or allocation row, by 'user'.
+// before and after are the row as json; before is null for inserts, after is
+// null for deletes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+  pub id: i64,
+  pub project: i64,
+  pub user: i64,
+  pub tablename: String,
+  pub recordid: i64,
+  pub action: AuditAction,
+  pub before: Option<serde_json::Value>,
+  pub after: Option<serde_json::Value>,
+  pub changedate: i64,
+}
+
+// deleted entries are kept until purged; changeddate is when they were deleted.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeletedEntries {
+  pub project: i64,
+  pub timeentries: Vec<TimeEntry>,
+  pub allocations: Vec<Allocation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RestoreEntries {
+  pub project: i64,
+  pub timeentries: Vec<i64>,
+  pub allocations: Vec<i64>,
+  // admins can restore locked time entries with this set.
+  pub unlock: Option<bool>,
+}
+
+// current rows for saves that were made over a newer change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectTimeConflicts {
+  pub timeentries: Vec<TimeEntry>,
+  pub payentries: Vec<PayEntry>,
+  pub allocations: Vec<Allocation>,
+}
+
+impl ProjectTimeConflicts {
+  pub fn is_empty(&self) -> bool {
+    self.timeentries.is_empty() && self.payentries.is_empty() && self.allocations.is_empty()
+  }
+}
+
+// the item in a SaveProjectTime that failed.  list is the field it's in, like
+// "savetimeentries".  none of the batch is saved.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectTimeError {
+  pub list: String,
+  pub index: usize,
+  pub id: Option<i64>,
+  pub error: String,
+  // for time entries that failed the checks.
+  #[serde(default)]
+  pub issues: Vec<TimeEntryIssue>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TimeEntryCheck {
+  EndBeforeStart,
+  ZeroLength,
+  FarFuture,
+  Overlap,
+}
+
+impl fmt::Display for TimeEntryCheck {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self)
+  }
+}
+
+// a problem with a time entry.  errors stop the entry being saved; warnings
+// don't.  for overlaps, other is the overlapping entry.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimeEntryIssue {
+  pub entry: Option<i64>,
+  pub check: TimeEntryCheck,
+  pub error: bool,
+  pub other: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SetProjectOverlaps {
+  pub project: i64,
+  pub reject: bool,
+}
+
+// a project entry that overlaps another entry for the same user, which may be
+// in another project.  startdate and enddate are the overlapping part.  other
+// and otherproject are none for projects the caller isn't a member of.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Overlap {
+  pub user: i64,
+  pub entry: i64,
+  pub other: Option<i64>,
+  pub otherproject: Option<i64>,
+  pub startdate: i64,
+  pub enddate: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OverlapReport {
+  pub project: i64,
+  pub overlaps: Vec<Overlap>,
+}
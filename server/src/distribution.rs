@@ -0,0 +1,417 @@
+use crate::data::{
+  Allocation, CalcDistribution, Distribution, DistributionStrategy, PayEntry, PayType, TimeEntry,
+  UserDistribution,
+};
+use crate::rates::{self, Rates};
+use crate::sqldata;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+
+pub const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+pub const MILLIS_PER_HOUR: i64 = 60 * 60 * 1000;
+
+// user -> millis
+type UserMillis = BTreeMap<i64, i64>;
+
+// split a time span into (day, millis) pieces.  days are UTC.
+pub fn millis_per_day(startdate: i64, enddate: i64) -> Vec<(i64, i64)> {
+  let mut v = Vec::new();
+  let mut s = startdate;
+  while s < enddate {
+    let day = s.div_euclid(MILLIS_PER_DAY);
+    let e = std::cmp::min((day + 1) * MILLIS_PER_DAY, enddate);
+    v.push((day, e - s));
+    s = e;
+  }
+  v
+}
+
+// total millis per day for each member, ignored entries excluded.
+pub fn team_millis_per_day(timeentries: &[TimeEntry]) -> BTreeMap<i64, UserMillis> {
+  let mut days: BTreeMap<i64, UserMillis> = BTreeMap::new();
+  for te in timeentries.iter().filter(|te| !te.ignore) {
+    for (day, millis) in millis_per_day(te.startdate, te.enddate) {
+      *days
+        .entry(day)
+        .or_insert_with(BTreeMap::new)
+        .entry(te.user)
+        .or_insert(0) += millis;
+    }
+  }
+  days
+}
+
+// total paid millis for each member.
+pub fn pay_totals(payentries: &[PayEntry]) -> UserMillis {
+  let mut totes = BTreeMap::new();
+  for pe in payentries {
+    match pe.paytype {
+      PayType::Paid => *totes.entry(pe.user).or_insert(0) += pe.duration,
+      PayType::Invoiced => (),
+    }
+  }
+  totes
+}
+
+// allocated millis that haven't been paid out yet.
+pub fn unpaid_allocation(payentries: &[PayEntry], allocations: &[Allocation]) -> i64 {
+  let allocated: i64 = allocations.iter().map(|a| a.duration).sum();
+  let paid: i64 = pay_totals(payentries).values().sum();
+  std::cmp::max(allocated - paid, 0)
+}
+
+// remove paid time from each member's days, oldest days first.
+// what's left is the unpaid time per day.
+pub fn unpaid_millis_per_day(
+  timeentries: &[TimeEntry],
+  payentries: &[PayEntry],
+) -> BTreeMap<i64, UserMillis> {
+  let mut ptotes = pay_totals(payentries);
+  let mut days = team_millis_per_day(timeentries);
+  for users in days.values_mut() {
+    users.retain(|user, millis| match ptotes.get_mut(user) {
+      Some(ptime) => {
+        if *ptime < *millis {
+          *millis -= *ptime;
+          *ptime = 0;
+          true
+        } else {
+          *ptime -= *millis;
+          false
+        }
+      }
+      None => true,
+    });
+  }
+  days.retain(|_, users| !users.is_empty());
+  days
+}
+
+// unpaid millis for each member, over all days.
+pub fn unpaid_totals(days: &BTreeMap<i64, UserMillis>) -> UserMillis {
+  let mut totes = BTreeMap::new();
+  for users in days.values() {
+    for (user, millis) in users {
+      *totes.entry(*user).or_insert(0) += millis;
+    }
+  }
+  totes
+}
+
+// 'below average' distribution.  the shortest time periods get paid first.
+// once all users are 'above average' then the remainder is split evenly
+// between them.
+// for example, if we have two users who worked 3 and 5 hours, and we want to
+// distribute 2 hours between them, then each would get 1 hour of pay.
+fn dist_below_avg(available: &UserMillis, sum: &mut UserMillis, distamt: i64) {
+  let mut users: Vec<(i64, i64)> = available.iter().map(|(u, m)| (*u, *m)).collect();
+  let mut da = distamt;
+  while !users.is_empty() && da > 0 {
+    let avg = da / users.len() as i64;
+    let (below, above): (Vec<(i64, i64)>, Vec<(i64, i64)>) =
+      users.iter().partition(|(_, millis)| *millis < avg);
+    if below.is_empty() {
+      // dist evenly to all users, with any leftover millis going one apiece
+      // to the first users.
+      let rem = da - avg * users.len() as i64;
+      for (i, (user, _)) in users.iter().enumerate() {
+        let extra = if (i as i64) < rem { 1 } else { 0 };
+        *sum.entry(*user).or_insert(0) += avg + extra;
+      }
+      return;
+    }
+    for (user, millis) in below {
+      *sum.entry(user).or_insert(0) += millis;
+      da -= millis;
+    }
+    users = above;
+  }
+}
+
+// distribute 'distmillis' of pay to members, earliest unpaid work first.
+// with a cap, no member is paid more than 'cap' millis.
+pub fn worked_first(
+  days: &BTreeMap<i64, UserMillis>,
+  distmillis: i64,
+  cap: Option<i64>,
+) -> UserMillis {
+  let mut sum: UserMillis = BTreeMap::new();
+  let mut distamt = distmillis;
+  for users in days.values() {
+    if distamt <= 0 {
+      break;
+    }
+    let day: UserMillis = match cap {
+      Some(c) => users
+        .iter()
+        .map(|(user, millis)| {
+          let paid = sum.get(user).copied().unwrap_or(0);
+          (*user, std::cmp::min(*millis, std::cmp::max(c - paid, 0)))
+        })
+        .filter(|(_, millis)| *millis > 0)
+        .collect(),
+      None => users.clone(),
+    };
+    let daysum: i64 = day.values().sum();
+    if daysum > distamt {
+      // last day; split what remains.
+      dist_below_avg(&day, &mut sum, distamt);
+      distamt = 0;
+    } else {
+      for (user, millis) in day {
+        *sum.entry(user).or_insert(0) += millis;
+      }
+      distamt -= daysum;
+    }
+  }
+  sum
+}
+
+// distribute in proportion to each member's unpaid time.
+pub fn pro_rata(totals: &UserMillis, distmillis: i64) -> UserMillis {
+  let total: i64 = totals.values().sum();
+  if total == 0 || distmillis <= 0 {
+    return BTreeMap::new();
+  }
+  if distmillis >= total {
+    return totals.clone();
+  }
+  let mut sum: UserMillis = totals
+    .iter()
+    .map(|(user, millis)| {
+      (
+        *user,
+        (*millis as i128 * distmillis as i128 / total as i128) as i64,
+      )
+    })
+    .collect();
+  // rounding leftovers go one milli apiece to members with room for them.
+  let mut rem = distmillis - sum.values().sum::<i64>();
+  for (user, millis) in totals {
+    if rem <= 0 {
+      break;
+    }
+    if let Some(s) = sum.get_mut(user) {
+      if *s < *millis {
+        *s += 1;
+        rem -= 1;
+      }
+    }
+  }
+  sum
+}
+
+// split evenly between members, no member getting more than their unpaid time.
+pub fn equal_split(totals: &UserMillis, distmillis: i64) -> UserMillis {
+  let mut sum = BTreeMap::new();
+  let total: i64 = totals.values().sum();
+  dist_below_avg(totals, &mut sum, std::cmp::min(distmillis, total));
+  sum
+}
+
+// value of 'millis' of a member's pay.  the oldest unpaid days are paid
+// first, each at its day_rate.
+pub fn dist_amount(
+  rates: &Rates,
+  days: &BTreeMap<i64, UserMillis>,
+  user: i64,
+  millis: i64,
+) -> Option<f64> {
+  let mut amount = None;
+  let mut left = millis;
+  for (day, users) in days {
+    if left <= 0 {
+      break;
+    }
+    if let Some(m) = users.get(&user) {
+      let dm = std::cmp::min(*m, left);
+      if let Some(r) = rates.day_rate(user, *day) {
+        amount = Some(amount.unwrap_or(0.0) + r * dm as f64 / MILLIS_PER_HOUR as f64);
+      }
+      left -= dm;
+    }
+  }
+  amount
+}
+
+// hours, as millis.  no negative or not-a-number hours.
+fn to_millis(hours: f64) -> Result<i64, orgauth::error::Error> {
+  if hours.is_finite() && hours >= 0.0 {
+    Ok((hours * MILLIS_PER_HOUR as f64).round() as i64)
+  } else {
+    Err(orgauth::error::Error::String(format!(
+      "invalid hours: {}",
+      hours
+    )))
+  }
+}
+
+pub fn calc_distribution(
+  project: i64,
+  strategy: DistributionStrategy,
+  rates: &Rates,
+  timeentries: &[TimeEntry],
+  payentries: &[PayEntry],
+  allocations: &[Allocation],
+  hours: Option<f64>,
+) -> Result<Distribution, orgauth::error::Error> {
+  // distribute the requested hours, or whatever allocation is left unpaid.
+  let distmillis = match hours {
+    Some(h) => to_millis(h)?,
+    None => unpaid_allocation(payentries, allocations),
+  };
+
+  let days = unpaid_millis_per_day(timeentries, payentries);
+  let sum = match strategy {
+    DistributionStrategy::WorkedFirst => worked_first(&days, distmillis, None),
+    DistributionStrategy::ProRata => pro_rata(&unpaid_totals(&days), distmillis),
+    DistributionStrategy::EqualSplit => equal_split(&unpaid_totals(&days), distmillis),
+    DistributionStrategy::Capped(caphours) => {
+      worked_first(&days, distmillis, Some(to_millis(caphours)?))
+    }
+  };
+
+  Ok(Distribution {
+    project: project,
+    strategy: strategy,
+    duration: sum.values().sum(),
+    users: sum
+      .into_iter()
+      .map(|(user, duration)| UserDistribution {
+        user: user,
+        duration: duration,
+        amount: dist_amount(rates, &days, user, duration),
+      })
+      .collect(),
+  })
+}
+
+pub fn project_distribution(
+  conn: &Connection,
+  cd: CalcDistribution,
+) -> Result<Distribution, orgauth::error::Error> {
+  let timeentries = sqldata::time_entries(conn, cd.project)?;
+  let payentries = sqldata::pay_entries(conn, cd.project)?;
+  let allocations = sqldata::allocations(conn, cd.project)?;
+  let strategy = match cd.strategy {
+    Some(s) => s,
+    None => sqldata::read_project(conn, cd.project)?.distribution,
+  };
+  let rates = rates::project_rates(conn, cd.project)?;
+  calc_distribution(
+    cd.project,
+    strategy,
+    &rates,
+    &timeentries,
+    &payentries,
+    &allocations,
+    cd.hours,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const H: i64 = MILLIS_PER_HOUR;
+
+  fn users(um: &[(i64, i64)]) -> UserMillis {
+    um.iter().copied().collect()
+  }
+
+  // day 0: user 1 works 3 hours.  day 1: user 1 works 1, user 2 works 2.
+  fn days() -> BTreeMap<i64, UserMillis> {
+    let mut days = BTreeMap::new();
+    days.insert(0, users(&[(1, 3 * H)]));
+    days.insert(1, users(&[(1, H), (2, 2 * H)]));
+    days
+  }
+
+  #[test]
+  fn worked_first_oldest_days_first() {
+    // day 0 is paid in full; the last hour is split on day 1.
+    assert_eq!(
+      worked_first(&days(), 4 * H, None),
+      users(&[(1, 3 * H + H / 2), (2, H / 2)])
+    );
+    // more than was worked pays all of it.
+    assert_eq!(
+      worked_first(&days(), 10 * H, None),
+      users(&[(1, 4 * H), (2, 2 * H)])
+    );
+  }
+
+  #[test]
+  fn capped() {
+    assert_eq!(
+      worked_first(&days(), 6 * H, Some(2 * H)),
+      users(&[(1, 2 * H), (2, 2 * H)])
+    );
+  }
+
+  #[test]
+  fn pro_rata_split() {
+    assert_eq!(
+      pro_rata(&users(&[(1, 3 * H), (2, H)]), 2 * H),
+      users(&[(1, 3 * H / 2), (2, H / 2)])
+    );
+    // leftover millis from rounding are still paid out.
+    assert_eq!(
+      pro_rata(&users(&[(1, 1), (2, 1), (3, 1)]), 2),
+      users(&[(1, 1), (2, 1), (3, 0)])
+    );
+    assert_eq!(pro_rata(&users(&[(1, H)]), 2 * H), users(&[(1, H)]));
+  }
+
+  #[test]
+  fn pro_rata_nothing_to_split() {
+    assert_eq!(pro_rata(&users(&[]), 2 * H), users(&[]));
+    assert_eq!(pro_rata(&users(&[(1, H)]), -H), users(&[]));
+  }
+
+  fn calc(strategy: DistributionStrategy, hours: Option<f64>) -> Option<Distribution> {
+    calc_distribution(
+      1,
+      strategy,
+      &Rates {
+        project: None,
+        members: Default::default(),
+        history: Vec::new(),
+      },
+      &[],
+      &[],
+      &[],
+      hours,
+    )
+    .ok()
+  }
+
+  #[test]
+  fn bad_hours() {
+    for h in vec![-1.0, f64::NAN, f64::INFINITY] {
+      assert!(calc(DistributionStrategy::ProRata, Some(h)).is_none());
+      assert!(calc(DistributionStrategy::Capped(h), Some(1.0)).is_none());
+    }
+    let d = calc(DistributionStrategy::ProRata, Some(1.0)).unwrap();
+    assert_eq!(d.duration, 0);
+    assert!(d.users.is_empty());
+  }
+
+  #[test]
+  fn equal_split_below_average() {
+    assert_eq!(
+      equal_split(&users(&[(1, 3 * H), (2, 5 * H)]), 2 * H),
+      users(&[(1, H), (2, H)])
+    );
+    // a member with less than their share gets all of theirs.
+    assert_eq!(
+      equal_split(&users(&[(1, H / 2), (2, 5 * H)]), 2 * H),
+      users(&[(1, H / 2), (2, 3 * H / 2)])
+    );
+    // no more than was worked.
+    assert_eq!(
+      equal_split(&users(&[(1, H), (2, H)]), 10 * H),
+      users(&[(1, H), (2, H)])
+    );
+  }
+}
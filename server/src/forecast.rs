@@ -0,0 +1,164 @@
+use crate::data::{Allocation, BurnPoint, Forecast, GetForecast, TimeEntry};
+use crate::distribution::MILLIS_PER_DAY;
+use crate::sqldata;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+
+pub const MILLIS_PER_WEEK: i64 = 7 * MILLIS_PER_DAY;
+pub const DEFAULT_FORECAST_WEEKS: i64 = 4;
+// ten years.
+pub const MAX_FORECAST_WEEKS: i64 = 520;
+
+// allocated hours remaining at the end of each day with allocations or work.
+// ignored time entries don't count.
+pub fn burndown(timeentries: &[TimeEntry], allocations: &[Allocation]) -> Vec<BurnPoint> {
+  let mut days: BTreeMap<i64, i64> = BTreeMap::new();
+  for a in allocations {
+    *days
+      .entry(a.allocationdate.div_euclid(MILLIS_PER_DAY))
+      .or_insert(0) += a.duration;
+  }
+  for te in timeentries.iter().filter(|te| !te.ignore) {
+    *days
+      .entry(te.startdate.div_euclid(MILLIS_PER_DAY))
+      .or_insert(0) -= te.enddate - te.startdate;
+  }
+
+  let mut remaining = 0;
+  days
+    .into_iter()
+    .map(|(day, change)| {
+      remaining += change;
+      BurnPoint {
+        date: day * MILLIS_PER_DAY,
+        remaining: remaining,
+      }
+    })
+    .collect()
+}
+
+// average millis worked per week over the 'weeks' before 'now'.
+pub fn burn_rate(timeentries: &[TimeEntry], now: i64, weeks: i64) -> i64 {
+  let from = now.saturating_sub(weeks.saturating_mul(MILLIS_PER_WEEK));
+  let worked: i64 = timeentries
+    .iter()
+    .filter(|te| !te.ignore)
+    .map(|te| {
+      // just the part of each entry within the window.
+      std::cmp::max(
+        std::cmp::min(te.enddate, now) - std::cmp::max(te.startdate, from),
+        0,
+      )
+    })
+    .sum();
+  worked / weeks
+}
+
+pub fn calc_forecast(
+  project: i64,
+  timeentries: &[TimeEntry],
+  allocations: &[Allocation],
+  now: i64,
+  weeks: i64,
+) -> Forecast {
+  let allocated: i64 = allocations.iter().map(|a| a.duration).sum();
+  let worked: i64 = timeentries
+    .iter()
+    .filter(|te| !te.ignore)
+    .map(|te| te.enddate - te.startdate)
+    .sum();
+  let remaining = allocated - worked;
+  let burnrate = burn_rate(timeentries, now, weeks);
+  let burndown = burndown(timeentries, allocations);
+
+  let exhaustiondate = if remaining <= 0 {
+    // already out; when did that happen?
+    burndown
+      .iter()
+      .rev()
+      .take_while(|bp| bp.remaining <= 0)
+      .last()
+      .map(|bp| bp.date)
+  } else if burnrate > 0 {
+    Some(now + (remaining as i128 * MILLIS_PER_WEEK as i128 / burnrate as i128) as i64)
+  } else {
+    None
+  };
+
+  Forecast {
+    project: project,
+    allocated: allocated,
+    worked: worked,
+    remaining: remaining,
+    weeks: weeks,
+    burnrate: burnrate,
+    exhaustiondate: exhaustiondate,
+    burndown: burndown,
+  }
+}
+
+pub fn project_forecast(
+  conn: &Connection,
+  gf: GetForecast,
+) -> Result<Forecast, orgauth::error::Error> {
+  let timeentries = sqldata::time_entries(conn, gf.project)?;
+  let allocations = sqldata::allocations(conn, gf.project)?;
+  let weeks = match gf.weeks {
+    Some(w) if w > 0 => std::cmp::min(w, MAX_FORECAST_WEEKS),
+    _ => DEFAULT_FORECAST_WEEKS,
+  };
+  Ok(calc_forecast(
+    gf.project,
+    &timeentries,
+    &allocations,
+    orgauth::util::now()?,
+    weeks,
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::distribution::MILLIS_PER_HOUR;
+
+  fn entry(startdate: i64, enddate: i64) -> TimeEntry {
+    TimeEntry {
+      id: 0,
+      project: 1,
+      user: 1,
+      description: String::new(),
+      startdate: startdate,
+      enddate: enddate,
+      ignore: false,
+      createdate: 0,
+      changeddate: 0,
+      creator: 1,
+      billed: false,
+      tags: Vec::new(),
+      task: None,
+    }
+  }
+
+  #[test]
+  fn burn_rate_window() {
+    let now = 100 * MILLIS_PER_WEEK;
+    let from = now - 2 * MILLIS_PER_WEEK;
+    let mut ignored = entry(now - 3 * MILLIS_PER_HOUR, now - MILLIS_PER_HOUR);
+    ignored.ignore = true;
+    let tes = vec![
+      entry(now - 4 * MILLIS_PER_HOUR, now),
+      // half in the window.
+      entry(from - MILLIS_PER_HOUR, from + MILLIS_PER_HOUR),
+      // before the window.
+      entry(from - 10 * MILLIS_PER_HOUR, from - 8 * MILLIS_PER_HOUR),
+      ignored,
+    ];
+    assert_eq!(burn_rate(&tes, now, 2), 5 * MILLIS_PER_HOUR / 2);
+  }
+
+  #[test]
+  fn burn_rate_huge_weeks() {
+    let tes = vec![entry(0, MILLIS_PER_HOUR)];
+    assert_eq!(burn_rate(&tes, MILLIS_PER_WEEK, i64::MAX), 0);
+  }
+}
@@ -0,0 +1,390 @@
+use crate::config::Config;
+use crate::data::{IcsImport, IcsImportEntry, SaveTimeEntry, TimeEntry};
+use crate::sqldata;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::TimeZone;
+use log::{error, info};
+use rusqlite::Connection;
+use std::collections::{HashMap, HashSet};
+
+// iCalendar text escaping, per rfc 5545.
+fn escape(s: &str) -> String {
+  s.replace('\\', "\\\\")
+    .replace(';', "\\;")
+    .replace(',', "\\,")
+    .replace("\r\n", "\\n")
+    .replace('\n', "\\n")
+}
+
+// content lines are folded at 75 octets, without splitting a character.
+fn fold(line: &str) -> String {
+  let mut out = String::new();
+  let mut len = 0;
+  for c in line.chars() {
+    if len + c.len_utf8() > 75 {
+      out.push_str("\r\n ");
+      len = 1;
+    }
+    out.push(c);
+    len += c.len_utf8();
+  }
+  out.push_str("\r\n");
+  out
+}
+
+fn ics_date(millis: i64) -> String {
+  chrono::Utc
+    .timestamp_millis_opt(millis)
+    .single()
+    .map(|d| d.format("%Y%m%dT%H%M%SZ").to_string())
+    .unwrap_or_default()
+}
+
+pub fn ics_calendar(
+  calname: &str,
+  appname: &str,
+  timeentries: &[TimeEntry],
+  projectnames: &HashMap<i64, String>,
+  usernames: &HashMap<i64, String>,
+) -> String {
+  let mut lines = vec![
+    "BEGIN:VCALENDAR".to_string(),
+    "VERSION:2.0".to_string(),
+    format!("PRODID:-//{}//time entries//EN", escape(appname)),
+    format!("X-WR-CALNAME:{}", escape(calname)),
+  ];
+  for te in timeentries {
+    let project = projectnames.get(&te.project).cloned().unwrap_or_default();
+    let user = usernames.get(&te.user).cloned().unwrap_or_default();
+    let summary = if te.description.is_empty() {
+      project.clone()
+    } else {
+      te.description.clone()
+    };
+    lines.push("BEGIN:VEVENT".to_string());
+    lines.push(format!("UID:timeentry-{}@{}", te.id, escape(appname)));
+    lines.push(format!("DTSTAMP:{}", ics_date(te.changeddate)));
+    lines.push(format!("DTSTART:{}", ics_date(te.startdate)));
+    lines.push(format!("DTEND:{}", ics_date(te.enddate)));
+    lines.push(format!("SUMMARY:{}", escape(summary.as_str())));
+    lines.push(format!(
+      "DESCRIPTION:{}",
+      escape(format!("{}\nproject: {}\nuser: {}", te.description, project, user).as_str())
+    ));
+    lines.push(format!("CATEGORIES:{}", escape(project.as_str())));
+    lines.push("END:VEVENT".to_string());
+  }
+  lines.push("END:VCALENDAR".to_string());
+
+  lines.iter().map(|l| fold(l.as_str())).collect()
+}
+
+// calendar text for a feed token, if the feed exists and the user can still
+// see the project.  a user feed has time from the projects the user is still
+// a member of.
+pub fn feed_calendar(
+  conn: &Connection,
+  appname: &str,
+  token: &str,
+) -> Result<Option<String>, orgauth::error::Error> {
+  let (uid, project) = match sqldata::ics_feed_for_token(conn, token)? {
+    Some(f) => f,
+    None => return Ok(None),
+  };
+
+  let usernames: HashMap<i64, String> = sqldata::user_list(conn)?
+    .into_iter()
+    .map(|u| (u.id, u.name))
+    .collect();
+
+  let (calname, timeentries) = match project {
+    Some(pid) => {
+      if sqldata::member_role(conn, uid, pid)?.is_none() {
+        return Ok(None);
+      }
+      (
+        sqldata::read_project(conn, pid)?.name,
+        sqldata::time_entries(conn, pid)?,
+      )
+    }
+    None => (
+      usernames.get(&uid).cloned().unwrap_or_default(),
+      sqldata::member_time(conn, uid)?,
+    ),
+  };
+
+  let mut projectnames = HashMap::new();
+  for te in timeentries.iter() {
+    if !projectnames.contains_key(&te.project) {
+      projectnames.insert(te.project, sqldata::read_project(conn, te.project)?.name);
+    }
+  }
+
+  Ok(Some(ics_calendar(
+    calname.as_str(),
+    appname,
+    &timeentries,
+    &projectnames,
+    &usernames,
+  )))
+}
+
+// GET /ics/{token}.ics.  the token is the authentication, so calendar clients
+// can subscribe without a session.
+pub async fn ics_feed(
+  config: web::Data<Config>,
+  path: web::Path<String>,
+  req: HttpRequest,
+) -> HttpResponse {
+  info!("ics feed, connection_info: {:?}", req.connection_info());
+  let token = path.trim_end_matches(".ics");
+  let cal = sqldata::connection_open(config.orgauth_config.db.as_path())
+    .and_then(|conn| feed_calendar(&conn, config.orgauth_config.appname.as_str(), token));
+  match cal {
+    Ok(Some(cal)) => HttpResponse::Ok()
+      .content_type("text/calendar; charset=utf-8")
+      .body(cal),
+    Ok(None) => HttpResponse::NotFound().finish(),
+    Err(e) => {
+      error!("'ics' err: {:?}", e);
+      HttpResponse::InternalServerError().finish()
+    }
+  }
+}
+
+// an event from an imported calendar.
+#[derive(Debug, Clone, Default)]
+pub struct IcsEvent {
+  pub summary: String,
+  pub description: String,
+  pub startdate: Option<i64>,
+  pub enddate: Option<i64>,
+  pub duration: Option<i64>,
+}
+
+fn unescape(s: &str) -> String {
+  let mut out = String::new();
+  let mut chars = s.chars();
+  while let Some(c) = chars.next() {
+    if c == '\\' {
+      match chars.next() {
+        Some('n') | Some('N') => out.push('\n'),
+        Some(e) => out.push(e),
+        None => (),
+      }
+    } else {
+      out.push(c);
+    }
+  }
+  out
+}
+
+// split a content line into name, params and value.  the value starts at the
+// first colon outside of a quoted param.
+fn content_line(line: &str) -> Option<(String, &str, &str)> {
+  let mut quoted = false;
+  for (i, c) in line.char_indices() {
+    match c {
+      '"' => quoted = !quoted,
+      ':' if !quoted => {
+        let (nameparams, value) = (&line[..i], &line[i + 1..]);
+        let (name, params) = match nameparams.find(';') {
+          Some(p) => (&nameparams[..p], &nameparams[p + 1..]),
+          None => (nameparams, ""),
+        };
+        return Some((name.to_uppercase(), params, value));
+      }
+      _ => (),
+    }
+  }
+  None
+}
+
+// date-times as millis.  floating times are taken to be UTC; TZID times are
+// rejected by parse_ics.  all-day dates give None; they aren't worked hours.
+fn parse_ics_date(value: &str) -> Option<i64> {
+  chrono::NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+    .ok()
+    .map(|d| chrono::Utc.from_utc_datetime(&d).timestamp_millis())
+}
+
+// durations like P1DT2H30M, as millis.
+fn parse_ics_duration(value: &str) -> Result<i64, String> {
+  let invalid = || format!("DURATION:{} is not a valid duration", value);
+  let (sign, rest) = match value.strip_prefix('-') {
+    Some(r) => (-1, r),
+    None => (1, value.trim_start_matches('+')),
+  };
+  let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+  let mut secs: i64 = 0;
+  let mut num = String::new();
+  for c in rest.chars() {
+    match c {
+      '0'..='9' => num.push(c),
+      'T' => (),
+      _ => {
+        let n: i64 = num.parse().map_err(|_| invalid())?;
+        num.clear();
+        let unit = match c {
+          'W' => 7 * 24 * 60 * 60,
+          'D' => 24 * 60 * 60,
+          'H' => 60 * 60,
+          'M' => 60,
+          'S' => 1,
+          _ => return Err(invalid()),
+        };
+        secs = n
+          .checked_mul(unit)
+          .and_then(|s| secs.checked_add(s))
+          .ok_or_else(invalid)?;
+      }
+    }
+  }
+  secs.checked_mul(sign * 1000).ok_or_else(invalid)
+}
+
+// errors on times in a named time zone, which would need the calendar's
+// VTIMEZONE rules to place correctly.
+pub fn parse_ics(ics: &str) -> Result<Vec<IcsEvent>, String> {
+  // unfold continuation lines first.
+  let unfolded = ics
+    .replace("\r\n", "\n")
+    .replace("\n ", "")
+    .replace("\n\t", "");
+
+  let mut events = Vec::new();
+  let mut event: Option<IcsEvent> = None;
+  for line in unfolded.lines() {
+    let (name, params, value) = match content_line(line) {
+      Some(l) => l,
+      None => continue,
+    };
+    if (name == "DTSTART" || name == "DTEND")
+      && event.is_some()
+      && params.to_uppercase().contains("TZID=")
+    {
+      return Err(format!(
+        "{}:{} has a time zone (TZID); only UTC times can be imported",
+        name, value
+      ));
+    }
+    match (name.as_str(), event.as_mut()) {
+      ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => event = Some(IcsEvent::default()),
+      ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+        if let Some(e) = event.take() {
+          events.push(e);
+        }
+      }
+      ("SUMMARY", Some(e)) => e.summary = unescape(value),
+      ("DESCRIPTION", Some(e)) => e.description = unescape(value),
+      ("DTSTART", Some(e)) => e.startdate = parse_ics_date(value),
+      ("DTEND", Some(e)) => e.enddate = parse_ics_date(value),
+      ("DURATION", Some(e)) => e.duration = Some(parse_ics_duration(value)?),
+      _ => (),
+    }
+  }
+  Ok(events)
+}
+
+// proposed time entries from a calendar file, marking any whose start time
+// the user already has an entry for, in any project.  a user can only have
+// one entry per start time.  errors if the calendar can't be imported.
+pub fn ics_import_preview(
+  conn: &Connection,
+  imp: &IcsImport,
+) -> Result<Result<Vec<IcsImportEntry>, String>, orgauth::error::Error> {
+  let events = match parse_ics(imp.ics.as_str()) {
+    Ok(events) => events,
+    Err(e) => return Ok(Err(e)),
+  };
+  let mut existing: HashSet<i64> = sqldata::user_time(conn, imp.user)?
+    .iter()
+    .map(|te| te.startdate)
+    .collect();
+  let keyword = imp.keyword.as_ref().map(|k| k.to_lowercase());
+
+  let mut entries = Vec::new();
+  for ev in events {
+    if let Some(k) = keyword.as_ref() {
+      if !ev.summary.to_lowercase().contains(k.as_str()) {
+        continue;
+      }
+    }
+    let (startdate, enddate) = match (ev.startdate, ev.enddate, ev.duration) {
+      (Some(s), Some(e), _) => (s, e),
+      (Some(s), None, Some(d)) => match s.checked_add(d) {
+        Some(e) => (s, e),
+        None => return Ok(Err(format!("'{}' ends too far from its start", ev.summary))),
+      },
+      _ => continue,
+    };
+    if enddate <= startdate {
+      continue;
+    }
+    let duplicate = !existing.insert(startdate);
+    entries.push(IcsImportEntry {
+      timeentry: SaveTimeEntry {
+        id: None,
+        project: imp.project,
+        user: imp.user,
+        description: ev.summary,
+        startdate: startdate,
+        enddate: enddate,
+        ignore: false,
+        tags: None,
+        task: None,
+        clear_task: false,
+        changeddate: None,
+      },
+      duplicate: duplicate,
+    });
+  }
+  Ok(Ok(entries))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_ics_tzid() {
+    let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:work\r\nDTSTART;TZID=Europe/Paris:20240101T090000\r\nDTEND;TZID=Europe/Paris:20240101T100000\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+    assert!(parse_ics(ics).is_err());
+  }
+
+  #[test]
+  fn parse_ics_events() {
+    let ics = "BEGIN:VCALENDAR\r\nSUMMARY:not in an event\r\nBEGIN:VEVENT\r\nSUMMARY:meeting\\, with\r\n  notes\r\nDTSTART:20240101T090000Z\r\nDTEND:20240101T103000Z\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nSUMMARY:call\r\nDTSTART:20240102T090000\r\nDURATION:PT1H30M\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nSUMMARY:holiday\r\nDTSTART;VALUE=DATE:20240103\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+    let events = parse_ics(ics).unwrap();
+    assert_eq!(events.len(), 3);
+    // unfolded and unescaped.
+    assert_eq!(events[0].summary, "meeting, with notes");
+    assert_eq!(events[0].startdate, Some(1704099600000));
+    assert_eq!(events[0].enddate, Some(1704105000000));
+    // floating times are UTC.
+    assert_eq!(events[1].startdate, Some(1704186000000));
+    assert_eq!(events[1].duration, Some(90 * 60 * 1000));
+    // all day.
+    assert_eq!(events[2].startdate, None);
+  }
+
+  #[test]
+  fn ics_durations() {
+    assert_eq!(
+      parse_ics_duration("P1DT2H30M"),
+      Ok((26 * 60 + 30) * 60 * 1000)
+    );
+    assert_eq!(parse_ics_duration("P1W"), Ok(7 * 24 * 60 * 60 * 1000));
+    assert_eq!(parse_ics_duration("-PT15M"), Ok(-15 * 60 * 1000));
+    assert!(parse_ics_duration("1H").is_err());
+  }
+
+  #[test]
+  fn oversized_durations() {
+    assert!(parse_ics_duration("P99999999999999W").is_err());
+    assert!(parse_ics_duration("PT9223372036854775807S").is_err());
+    assert!(parse_ics_duration("P110000000000D").is_err());
+    let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:forever\r\nDTSTART:20240101T090000Z\r\nDURATION:P99999999999999W\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+    assert!(parse_ics(ics).is_err());
+  }
+}
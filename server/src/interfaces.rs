@@ -1,9 +1,19 @@
 use crate::config::Config;
-use crate::data::{Role, SaveProjectEdit, SaveProjectInvoice, SaveProjectTime};
+use crate::csvdata;
+use crate::data::{
+  CalcDistribution, ClockIn, ClockOut, Distribution, ExportCsv, GetForecast, GetInvoiceTime,
+  IcsImport, ImportCsv, InvoiceTime, NewApiToken, NewIcsFeed, ProjectMember, ProjectRate,
+  ProjectTime, RestoreEntries, Role, SaveProjectEdit, SaveProjectInvoice, SaveProjectTime,
+  SaveTask, SetInvoiceStatus, SetProjectAlert, SetProjectLock, SetProjectOverlaps, SetProjectRate,
+};
+use crate::distribution;
+use crate::forecast;
+use crate::ics;
 use crate::messages::{PublicMessage, ServerResponse, UserMessage};
 use crate::sqldata;
 use actix_session::Session;
 use log::info;
+use rusqlite::{Transaction, TransactionBehavior};
 use std::error::Error;
 
 pub fn login_data_for_token(
@@ -58,11 +68,23 @@ pub fn timeclonk_interface_loggedin(
       };
 
       if allowed {
-        let saved = sqldata::save_project_edit(&conn, uid, sp)?;
-        Ok(ServerResponse {
-          what: "savedprojectedit".to_string(),
-          content: serde_json::to_value(saved)?,
-        })
+        // the conflict check and the save in one transaction, so nothing
+        // changes in between.
+        let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)?;
+        match sqldata::project_edit_conflict(&tx, &sp)? {
+          Some(current) => Ok(ServerResponse {
+            what: "saveprojectedit_conflict".to_string(),
+            content: serde_json::to_value(current)?,
+          }),
+          None => {
+            let saved = sqldata::save_project_edit(&tx, uid, sp)?;
+            tx.commit()?;
+            Ok(ServerResponse {
+              what: "savedprojectedit".to_string(),
+              content: serde_json::to_value(saved)?,
+            })
+          }
+        }
       } else {
         Ok(ServerResponse {
           what: "saveprojectedit_denied".to_string(),
@@ -74,12 +96,14 @@ pub fn timeclonk_interface_loggedin(
       let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
       let pid: i64 = serde_json::from_value(msgdata.clone())?;
       let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
-      let allowed = match sqldata::member_role(&conn, uid, pid)? {
+      let role = sqldata::member_role(&conn, uid, pid)?;
+      let allowed = match role {
         Some(_) => true, // any role is ok
         _ => false,
       };
       if allowed {
-        let project = sqldata::read_project_edit(&conn, pid)?;
+        let mut project = sqldata::read_project_edit(&conn, pid)?;
+        hide_rates(&mut project.members, &role);
 
         Ok(ServerResponse {
           what: "projectedit".to_string(),
@@ -116,7 +140,7 @@ pub fn timeclonk_interface_loggedin(
         })
       }
     }
-    "GetProjectTime" => {
+    "ListInvoices" => {
       let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
       let pid: i64 = serde_json::from_value(msgdata.clone())?;
       let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
@@ -126,7 +150,108 @@ pub fn timeclonk_interface_loggedin(
       };
 
       if allowed {
-        let project = sqldata::read_project_time(&conn, pid)?;
+        let invoices = sqldata::invoices(&conn, pid)?;
+
+        Ok(ServerResponse {
+          what: "invoices".to_string(),
+          content: serde_json::to_value(invoices)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "invoices_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetInvoiceTime" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let git: GetInvoiceTime = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let role = sqldata::member_role(&conn, uid, git.project)?;
+      let allowed = match role {
+        Some(Role::Admin) => true,
+        Some(Role::Member) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let tes = invoice_rates(
+          sqldata::invoice_time(&conn, git.project, git.include_billed.unwrap_or(false))?,
+          &role,
+        );
+
+        Ok(ServerResponse {
+          what: "invoicetime".to_string(),
+          content: serde_json::to_value(tes)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "invoicetime_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetInvoice" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let id: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let invoice = match sqldata::read_invoice(&conn, id)? {
+        Some(invoice) => match sqldata::member_role(&conn, uid, invoice.project)? {
+          Some(_) => Some(invoice), // any role is ok
+          _ => None,
+        },
+        None => None,
+      };
+
+      match invoice {
+        Some(invoice) => Ok(ServerResponse {
+          what: "invoice".to_string(),
+          content: serde_json::to_value(invoice)?,
+        }),
+        None => Ok(ServerResponse {
+          what: "invoice_denied".to_string(),
+          content: serde_json::Value::Null,
+        }),
+      }
+    }
+    "SetInvoiceStatus" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let sis: SetInvoiceStatus = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::read_invoice(&conn, sis.id)? {
+        Some(invoice) => match sqldata::member_role(&conn, uid, invoice.project)? {
+          Some(Role::Admin) => invoice.status.can_change_to(&sis.status),
+          _ => false,
+        },
+        None => false,
+      };
+
+      if allowed {
+        sqldata::set_invoice_status(&conn, sis.id, sis.status)?;
+
+        Ok(ServerResponse {
+          what: "invoice".to_string(),
+          content: serde_json::to_value(sqldata::read_invoice(&conn, sis.id)?)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "invoicestatus_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetProjectTime" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let role = sqldata::member_role(&conn, uid, pid)?;
+      let allowed = match role {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
+
+      if allowed {
+        let project = member_rates(sqldata::read_project_time(&conn, pid)?, &role);
 
         Ok(ServerResponse {
           what: "projecttime".to_string(),
@@ -140,27 +265,594 @@ pub fn timeclonk_interface_loggedin(
       }
     }
     "SaveProjectTime" => {
-      // TODO:
       let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
       let spt: SaveProjectTime = serde_json::from_value(msgdata.clone())?;
       let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      save_project_time(&conn, uid, spt)
+    }
+    "PreviewIcsImport" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let imp: IcsImport = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+
+      if ics_import_allowed(&conn, uid, &imp)? {
+        match ics::ics_import_preview(&conn, &imp)? {
+          Ok(entries) => Ok(ServerResponse {
+            what: "icsimportpreview".to_string(),
+            content: serde_json::to_value(entries)?,
+          }),
+          Err(e) => Ok(ServerResponse {
+            what: "icsimport_failed".to_string(),
+            content: serde_json::to_value(e)?,
+          }),
+        }
+      } else {
+        Ok(ServerResponse {
+          what: "icsimport_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "ImportIcs" => {
+      // imports the non-duplicate entries from the preview.
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let imp: IcsImport = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+
+      if ics_import_allowed(&conn, uid, &imp)? {
+        let entries = match ics::ics_import_preview(&conn, &imp)? {
+          Ok(entries) => entries,
+          Err(e) => {
+            return Ok(ServerResponse {
+              what: "icsimport_failed".to_string(),
+              content: serde_json::to_value(e)?,
+            })
+          }
+        };
+        let spt = SaveProjectTime {
+          project: imp.project,
+          savetimeentries: entries
+            .into_iter()
+            .filter(|e| !e.duplicate)
+            .map(|e| e.timeentry)
+            .collect(),
+          deletetimeentries: Vec::new(),
+          savepayentries: Vec::new(),
+          deletepayentries: Vec::new(),
+          saveallocations: Vec::new(),
+          deleteallocations: Vec::new(),
+          unlock: None,
+        };
+        save_project_time(&conn, uid, spt)
+      } else {
+        Ok(ServerResponse {
+          what: "icsimport_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "ExportCsv" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let ec: ExportCsv = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, ec.project)? {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
 
-      let allowed = match sqldata::member_role(&conn, uid, spt.project)? {
+      if allowed {
+        let csv = csvdata::export_csv(&conn, ec.project, ec.kind)?;
+
+        Ok(ServerResponse {
+          what: "csv".to_string(),
+          content: serde_json::Value::String(csv),
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "csv_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "ImportCsv" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let ic: ImportCsv = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, ic.project)? {
         Some(Role::Admin) => true,
         Some(Role::Member) => true,
         _ => false,
       };
 
       if allowed {
-        let bak = sqldata::save_project_time(&conn, uid, spt)?;
+        // nothing is saved unless every row is ok.
+        match csvdata::import_csv(&conn, ic.project, ic.kind, ic.csv.as_str())? {
+          Ok(spt) => save_project_time(&conn, uid, spt),
+          Err(errors) => Ok(ServerResponse {
+            what: "csvimport_errors".to_string(),
+            content: serde_json::to_value(errors)?,
+          }),
+        }
+      } else {
+        Ok(ServerResponse {
+          what: "projecttime_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetProjectTags" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, pid)? {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
+
+      if allowed {
+        let tags = sqldata::project_tags(&conn, pid)?;
 
         Ok(ServerResponse {
-          what: "projecttime".to_string(),
-          content: serde_json::to_value(bak)?,
+          what: "projecttags".to_string(),
+          content: serde_json::to_value(tags)?,
         })
       } else {
         Ok(ServerResponse {
-          what: "projecttime_denied".to_string(),
+          what: "projecttags_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetTagReport" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, pid)? {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
+
+      if allowed {
+        let report = sqldata::tag_report(&conn, pid)?;
+
+        Ok(ServerResponse {
+          what: "tagreport".to_string(),
+          content: serde_json::to_value(report)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "tagreport_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetProjectTasks" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, pid)? {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
+
+      if allowed {
+        let tasks = sqldata::tasks(&conn, pid)?;
+
+        Ok(ServerResponse {
+          what: "projecttasks".to_string(),
+          content: serde_json::to_value(tasks)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "projecttasks_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "SaveTask" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let st: SaveTask = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, st.project)? {
+        Some(Role::Admin) => true,
+        Some(Role::Member) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let task = sqldata::save_task(&conn, uid, st)?;
+
+        Ok(ServerResponse {
+          what: "savedtask".to_string(),
+          content: serde_json::to_value(task)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "savetask_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "DeleteTask" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let id: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let task = sqldata::read_task(&conn, id)?;
+      let allowed = match sqldata::member_role(&conn, uid, task.project)? {
+        Some(Role::Admin) => true,
+        _ => false,
+      };
+
+      if allowed {
+        match sqldata::delete_task(&conn, uid, task.project, id)? {
+          Ok(()) => Ok(ServerResponse {
+            what: "projecttasks".to_string(),
+            content: serde_json::to_value(sqldata::tasks(&conn, task.project)?)?,
+          }),
+          Err(locked) => Ok(ServerResponse {
+            what: "deletetask_locked".to_string(),
+            content: serde_json::to_value(locked)?,
+          }),
+        }
+      } else {
+        Ok(ServerResponse {
+          what: "deletetask_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetTaskReport" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, pid)? {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
+
+      if allowed {
+        let report = sqldata::task_report(&conn, pid)?;
+
+        Ok(ServerResponse {
+          what: "taskreport".to_string(),
+          content: serde_json::to_value(report)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "taskreport_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "CalcDistribution" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let cd: CalcDistribution = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let role = sqldata::member_role(&conn, uid, cd.project)?;
+      let allowed = match role {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
+
+      if allowed {
+        let dist = distribution_rates(distribution::project_distribution(&conn, cd)?, &role);
+
+        Ok(ServerResponse {
+          what: "distribution".to_string(),
+          content: serde_json::to_value(dist)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "distribution_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetForecast" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let gf: GetForecast = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, gf.project)? {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
+
+      if allowed {
+        let forecast = forecast::project_forecast(&conn, gf)?;
+
+        Ok(ServerResponse {
+          what: "forecast".to_string(),
+          content: serde_json::to_value(forecast)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "forecast_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "ClockIn" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let ci: ClockIn = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+
+      let allowed = match sqldata::member_role(&conn, uid, ci.project)? {
+        Some(Role::Admin) => true,
+        Some(Role::Member) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let at = sqldata::clock_in(&conn, uid, ci)?;
+
+        Ok(ServerResponse {
+          what: "clockedin".to_string(),
+          content: serde_json::to_value(at)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "clockin_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "ClockOut" => {
+      let co: ClockOut = match msg.data.as_ref() {
+        Some(msgdata) => serde_json::from_value(msgdata.clone())?,
+        None => ClockOut { description: None },
+      };
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      match sqldata::clock_out(&conn, uid, co)? {
+        Some(te) => Ok(ServerResponse {
+          what: "clockedout".to_string(),
+          content: serde_json::to_value(te)?,
+        }),
+        None => Ok(ServerResponse {
+          what: "clockout_notrunning".to_string(),
+          content: serde_json::Value::Null,
+        }),
+      }
+    }
+    "GetActiveTimers" => {
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let timers = sqldata::active_timers(&conn, uid)?;
+      Ok(ServerResponse {
+        what: "activetimers".to_string(),
+        content: serde_json::to_value(timers)?,
+      })
+    }
+    "SetProjectLock" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let spl: SetProjectLock = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, spl.project)? {
+        Some(Role::Admin) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let project = sqldata::set_project_lock(&conn, spl.project, spl.lockdate)?;
+
+        Ok(ServerResponse {
+          what: "project".to_string(),
+          content: serde_json::to_value(project)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "projectlock_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "SetProjectOverlaps" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let spo: SetProjectOverlaps = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, spo.project)? {
+        Some(Role::Admin) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let project = sqldata::set_project_overlaps(&conn, spo.project, spo.reject)?;
+
+        Ok(ServerResponse {
+          what: "project".to_string(),
+          content: serde_json::to_value(project)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "projectoverlaps_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "FindOverlaps" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, pid)? {
+        Some(Role::Admin) => true,
+        Some(Role::Member) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let report = sqldata::find_overlaps(&conn, uid, pid)?;
+
+        Ok(ServerResponse {
+          what: "overlapreport".to_string(),
+          content: serde_json::to_value(report)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "overlapreport_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "SetProjectAlert" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let spa: SetProjectAlert = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, spa.project)? {
+        Some(Role::Admin) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let project = sqldata::set_project_alert(&conn, spa.project, spa.threshold)?;
+
+        Ok(ServerResponse {
+          what: "project".to_string(),
+          content: serde_json::to_value(project)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "projectalert_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetDeletedEntries" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, pid)? {
+        Some(Role::Admin) => true,
+        Some(Role::Member) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let deleted = sqldata::deleted_entries(&conn, pid)?;
+
+        Ok(ServerResponse {
+          what: "deletedentries".to_string(),
+          content: serde_json::to_value(deleted)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "deletedentries_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "RestoreEntries" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let re: RestoreEntries = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let role = sqldata::member_role(&conn, uid, re.project)?;
+      let allowed = match role {
+        Some(Role::Admin) => true,
+        Some(Role::Member) => true,
+        _ => false,
+      };
+
+      // same lock rules as saving, checked in the restore's transaction.
+      let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)?;
+      let locked = match (&role, re.unlock) {
+        (Some(Role::Admin), Some(true)) => Vec::new(),
+        _ => sqldata::locked_restores(&tx, &re)?,
+      };
+
+      if allowed && !locked.is_empty() {
+        Ok(ServerResponse {
+          what: "projecttime_locked".to_string(),
+          content: serde_json::to_value(locked)?,
+        })
+      } else if allowed {
+        match sqldata::restore_entries(&tx, uid, &re)? {
+          Ok(()) => {
+            tx.commit()?;
+            let pt = member_rates(sqldata::read_project_time(&conn, re.project)?, &role);
+            Ok(ServerResponse {
+              what: "projecttime".to_string(),
+              content: serde_json::to_value(pt)?,
+            })
+          }
+          // time entries whose start time is in use again, or that fail checks.
+          Err(taken) => Ok(ServerResponse {
+            what: "restoreentries_conflict".to_string(),
+            content: serde_json::to_value(taken)?,
+          }),
+        }
+      } else {
+        Ok(ServerResponse {
+          what: "restoreentries_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetProjectAudit" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, pid)? {
+        Some(Role::Admin) => true,
+        _ => false,
+      };
+
+      if allowed {
+        let audit = sqldata::project_audit(&conn, pid)?;
+
+        Ok(ServerResponse {
+          what: "projectaudit".to_string(),
+          content: serde_json::to_value(audit)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "projectaudit_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "GetProjectRates" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let pid: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let role = sqldata::member_role(&conn, uid, pid)?;
+      let allowed = match role {
+        Some(_) => true, // any role is ok
+        _ => false,
+      };
+
+      if allowed {
+        let rates = rate_history(sqldata::project_rate_history(&conn, pid)?, &role);
+
+        Ok(ServerResponse {
+          what: "projectrates".to_string(),
+          content: serde_json::to_value(rates)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "projectrates_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "SetProjectRate" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let spr: SetProjectRate = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match sqldata::member_role(&conn, uid, spr.project)? {
+        Some(Role::Admin) => match spr.user {
+          Some(user) => sqldata::is_project_member(&conn, user, spr.project)?,
+          None => true,
+        },
+        _ => false,
+      };
+
+      if allowed {
+        let rates = sqldata::set_project_rate(&conn, uid, spr)?;
+
+        Ok(ServerResponse {
+          what: "projectrates".to_string(),
+          content: serde_json::to_value(rates)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "projectrate_denied".to_string(),
           content: serde_json::Value::Null,
         })
       }
@@ -173,6 +865,75 @@ pub fn timeclonk_interface_loggedin(
         content: serde_json::to_value(time)?,
       })
     }
+    "GetApiTokens" => {
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let tokens = sqldata::api_tokens(&conn, uid)?;
+      Ok(ServerResponse {
+        what: "apitokens".to_string(),
+        content: serde_json::to_value(tokens)?,
+      })
+    }
+    "CreateApiToken" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let nat: NewApiToken = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let cat = sqldata::create_api_token(&conn, uid, nat)?;
+      Ok(ServerResponse {
+        what: "apitokencreated".to_string(),
+        content: serde_json::to_value(cat)?,
+      })
+    }
+    "RevokeApiToken" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let id: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let tokens = sqldata::revoke_api_token(&conn, uid, id)?;
+      Ok(ServerResponse {
+        what: "apitokens".to_string(),
+        content: serde_json::to_value(tokens)?,
+      })
+    }
+    "GetIcsFeeds" => {
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let feeds = sqldata::ics_feeds(&conn, uid)?;
+      Ok(ServerResponse {
+        what: "icsfeeds".to_string(),
+        content: serde_json::to_value(feeds)?,
+      })
+    }
+    "CreateIcsFeed" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let nif: NewIcsFeed = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let allowed = match nif.project {
+        Some(pid) => sqldata::member_role(&conn, uid, pid)?.is_some(), // any role is ok
+        None => true,
+      };
+
+      if allowed {
+        let cif =
+          sqldata::create_ics_feed(&conn, uid, config.orgauth_config.mainsite.as_str(), nif)?;
+        Ok(ServerResponse {
+          what: "icsfeedcreated".to_string(),
+          content: serde_json::to_value(cif)?,
+        })
+      } else {
+        Ok(ServerResponse {
+          what: "icsfeed_denied".to_string(),
+          content: serde_json::Value::Null,
+        })
+      }
+    }
+    "RevokeIcsFeed" => {
+      let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
+      let id: i64 = serde_json::from_value(msgdata.clone())?;
+      let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
+      let feeds = sqldata::revoke_ics_feed(&conn, uid, id)?;
+      Ok(ServerResponse {
+        what: "icsfeeds".to_string(),
+        content: serde_json::to_value(feeds)?,
+      })
+    }
     "GetAllUsers" => {
       // all users can see all users!
       let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
@@ -190,6 +951,141 @@ pub fn timeclonk_interface_loggedin(
   }
 }
 
+// save time, pay and allocations, unless the user lacks permission or the
+// changes touch locked time entries.
+fn save_project_time(
+  conn: &rusqlite::Connection,
+  uid: i64,
+  spt: SaveProjectTime,
+) -> Result<ServerResponse, Box<dyn Error>> {
+  let role = sqldata::member_role(conn, uid, spt.project)?;
+  let allowed = match role {
+    Some(Role::Admin) => true,
+    Some(Role::Member) => true,
+    _ => false,
+  };
+
+  if allowed {
+    // lock and conflict checks in the same transaction as the writes, so
+    // nothing changes in between.
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+    let sr = save_project_time_checked(&tx, uid, role, spt)?;
+    tx.commit()?;
+    Ok(sr)
+  } else {
+    Ok(ServerResponse {
+      what: "projecttime_denied".to_string(),
+      content: serde_json::Value::Null,
+    })
+  }
+}
+
+fn save_project_time_checked(
+  conn: &rusqlite::Connection,
+  uid: i64,
+  role: Option<Role>,
+  spt: SaveProjectTime,
+) -> Result<ServerResponse, Box<dyn Error>> {
+  // only admins can change locked entries, and only when they ask to.
+  let unlock = match (&role, spt.unlock) {
+    (Some(Role::Admin), Some(true)) => true,
+    _ => false,
+  };
+  let locked = match unlock {
+    true => Vec::new(),
+    false => sqldata::locked_time_entries(conn, &spt)?,
+  };
+
+  if !locked.is_empty() {
+    return Ok(ServerResponse {
+      what: "projecttime_locked".to_string(),
+      content: serde_json::to_value(locked)?,
+    });
+  }
+
+  let conflicts = sqldata::project_time_conflicts(conn, &spt)?;
+
+  if !conflicts.is_empty() {
+    Ok(ServerResponse {
+      what: "projecttime_conflict".to_string(),
+      content: serde_json::to_value(conflicts)?,
+    })
+  } else {
+    match sqldata::save_project_time(conn, uid, spt, unlock)? {
+      Ok(bak) => Ok(ServerResponse {
+        what: "projecttime".to_string(),
+        content: serde_json::to_value(member_rates(bak, &role))?,
+      }),
+      Err(e) => Ok(ServerResponse {
+        what: "projecttime_failed".to_string(),
+        content: serde_json::to_value(e)?,
+      }),
+    }
+  }
+}
+
+// member rates are only for project admins.
+fn rates_shown(role: &Option<Role>) -> bool {
+  match role {
+    Some(Role::Admin) => true,
+    _ => false,
+  }
+}
+
+fn hide_rates(members: &mut [ProjectMember], role: &Option<Role>) {
+  if !rates_shown(role) {
+    for m in members.iter_mut() {
+      m.rate = None;
+    }
+  }
+}
+
+fn member_rates(mut pt: ProjectTime, role: &Option<Role>) -> ProjectTime {
+  hide_rates(&mut pt.members, role);
+  pt
+}
+
+// project rate changes for everyone; member rate changes for admins.
+fn rate_history(rates: Vec<ProjectRate>, role: &Option<Role>) -> Vec<ProjectRate> {
+  if rates_shown(role) {
+    rates
+  } else {
+    rates.into_iter().filter(|r| r.user.is_none()).collect()
+  }
+}
+
+// invoice items are priced at member rates.
+fn invoice_rates(mut it: InvoiceTime, role: &Option<Role>) -> InvoiceTime {
+  if !rates_shown(role) {
+    it.items = Vec::new();
+  }
+  it
+}
+
+// amount over duration gives the rate away.
+fn distribution_rates(mut dist: Distribution, role: &Option<Role>) -> Distribution {
+  if !rates_shown(role) {
+    for u in dist.users.iter_mut() {
+      u.amount = None;
+    }
+  }
+  dist
+}
+
+// calendar imports are for project members, adding time for a member.
+fn ics_import_allowed(
+  conn: &rusqlite::Connection,
+  uid: i64,
+  imp: &IcsImport,
+) -> Result<bool, Box<dyn Error>> {
+  let allowed = match sqldata::member_role(conn, uid, imp.project)? {
+    Some(Role::Admin) => true,
+    Some(Role::Member) => true,
+    _ => false,
+  };
+  Ok(allowed && sqldata::is_project_member(conn, imp.user, imp.project)?)
+}
+
 // public json msgs don't require login.
 pub fn public_interface(
   config: &Config,
@@ -201,7 +1097,7 @@ pub fn public_interface(
       let msgdata = Option::ok_or(msg.data.as_ref(), "malformed json data")?;
       let pid: i64 = serde_json::from_value(msgdata.clone())?;
       let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
-      let project = sqldata::read_project_time(&conn, pid)?;
+      let project = member_rates(sqldata::read_project_time(&conn, pid)?, &None);
 
       if project.project.public {
         Ok(ServerResponse {
@@ -221,3 +1117,86 @@ pub fn public_interface(
     )))),
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::data::{DistributionStrategy, InvoiceItem, UserDistribution};
+
+  fn members() -> Vec<ProjectMember> {
+    vec![ProjectMember {
+      id: 1,
+      name: "member".to_string(),
+      role: Role::Member,
+      rate: Some(20.0),
+    }]
+  }
+
+  fn dist() -> Distribution {
+    Distribution {
+      project: 1,
+      strategy: DistributionStrategy::WorkedFirst,
+      duration: 1,
+      users: vec![UserDistribution {
+        user: 1,
+        duration: 1,
+        amount: Some(20.0),
+      }],
+    }
+  }
+
+  fn invoice_time() -> InvoiceTime {
+    InvoiceTime {
+      timeentries: Vec::new(),
+      items: vec![InvoiceItem {
+        description: "member".to_string(),
+        duration: 1.0,
+        rate: 20.0,
+      }],
+    }
+  }
+
+  #[test]
+  fn rates_for_admins() {
+    let admin = Some(Role::Admin);
+    let mut ms = members();
+    hide_rates(&mut ms, &admin);
+    assert_eq!(ms[0].rate, Some(20.0));
+    assert_eq!(
+      distribution_rates(dist(), &admin).users[0].amount,
+      Some(20.0)
+    );
+    assert_eq!(invoice_rates(invoice_time(), &admin).items.len(), 1);
+  }
+
+  #[test]
+  fn rates_hidden() {
+    for role in vec![Some(Role::Member), Some(Role::Observer), None] {
+      let mut ms = members();
+      hide_rates(&mut ms, &role);
+      assert_eq!(ms[0].rate, None);
+      assert_eq!(distribution_rates(dist(), &role).users[0].amount, None);
+      assert!(invoice_rates(invoice_time(), &role).items.is_empty());
+    }
+  }
+
+  #[test]
+  fn rate_history_members() {
+    let rate = |user: Option<i64>| ProjectRate {
+      id: 1,
+      project: 1,
+      user: user,
+      rate: Some(20.0),
+      effectivedate: 0,
+      createdate: 0,
+      creator: 1,
+    };
+    let rates = vec![rate(None), rate(Some(1))];
+    assert_eq!(rate_history(rates.clone(), &Some(Role::Admin)).len(), 2);
+    for role in vec![Some(Role::Member), Some(Role::Observer)] {
+      let shown = rate_history(rates.clone(), &role);
+      assert_eq!(shown.len(), 1);
+      assert_eq!(shown[0].user, None);
+    }
+  }
+}
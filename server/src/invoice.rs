@@ -1,24 +1,30 @@
 use crate::config::Config;
-use crate::data::{InvoiceItem, PrintInvoice};
+use crate::data::{InvoiceItem, PrintInvoice, Role};
 use crate::sqldata;
-use actix_files::NamedFile;
 use actix_session::Session;
 use actix_web::{
   error::{ErrorInternalServerError, ErrorUnauthorized},
-  web, HttpRequest,
+  web, HttpRequest, HttpResponse,
 };
-use std::path::PathBuf;
-use std::process::Command;
+use chrono::Datelike;
+use comemo::Prehashed;
+use std::sync::OnceLock;
+use typst::diag::{FileError, FileResult};
+use typst::foundations::{Bytes, Datetime, Smart};
+use typst::syntax::{FileId, Source, VirtualPath};
+use typst::text::{Font, FontBook};
+use typst::Library;
 use uuid::Uuid;
 
-pub const INVOICE_DIR: &str = "invoices";
+// the invoice template is built into the server.
+pub const INVOICE_TEMPLATE: &str = include_str!("../invoice.typ");
 
 pub async fn invoice(
   session: Session,
   config: web::Data<Config>,
   item: web::Json<PrintInvoice>,
   _req: HttpRequest,
-) -> actix_web::Result<NamedFile> {
+) -> actix_web::Result<HttpResponse> {
   // we logged in?  to prevent randos from making invoices
   let token = match session.get::<Uuid>("token")? {
     None => {
@@ -33,7 +39,7 @@ pub async fn invoice(
     Err(e) => return Err(ErrorInternalServerError(e)),
     Ok(c) => c,
   };
-  let _user = match orgauth::dbfun::read_user_by_token_api(
+  let user = match orgauth::dbfun::read_user_by_token_api(
     &conn,
     token,
     config.orgauth_config.login_token_expiration_ms,
@@ -45,32 +51,192 @@ pub async fn invoice(
     Ok(u) => u,
   };
 
-  let path = run_invoice(item.0).map_err(|e| ErrorInternalServerError(e.to_string()))?; // .map_err(|e| actix_web::Error::fmt(, )
+  // recording the invoice?  members and admins only.
+  if let Some(pid) = item.0.project {
+    match sqldata::member_role(&conn, user.id, pid) {
+      Ok(Some(Role::Admin)) => (),
+      Ok(Some(Role::Member)) => (),
+      Ok(_) => {
+        return Err(ErrorUnauthorized(orgauth::error::Error::String(
+          "not a project member".to_string(),
+        )))
+      }
+      Err(e) => return Err(ErrorInternalServerError(e)),
+    }
+  }
 
-  Ok(NamedFile::open(path)?)
-}
+  let filename = pdf_filename(item.0.id.as_str());
+  let pdf = run_invoice(item.0.clone()).map_err(|e| ErrorInternalServerError(e.to_string()))?;
 
-pub fn invoice_str(item: &InvoiceItem) -> String {
-  format!(
-    "
-    (
-      item: \"{}\",
-      dur-min: 0,
-      hours: {},
-      rate: {},
-    ),
-    ",
-    item.description, item.duration, item.rate
+  if let Some(pid) = item.0.project {
+    sqldata::save_invoice(&conn, user.id, pid, &item.0).map_err(ErrorInternalServerError)?;
+  }
+
+  Ok(
+    HttpResponse::Ok()
+      .content_type("application/pdf")
+      .insert_header((
+        "Content-Disposition",
+        format!("inline; filename=\"{}\"", filename),
+      ))
+      .body(pdf),
   )
 }
 
-pub fn run_invoice(print_invoice: PrintInvoice) -> Result<PathBuf, orgauth::error::Error> {
+// invoice numbers are user text, so keep the filename to safe characters.
+fn pdf_filename(id: &str) -> String {
+  let name: String = id
+    .chars()
+    .map(|c| match c {
+      'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+      _ => '_',
+    })
+    .collect();
+  if name.is_empty() {
+    "invoice.pdf".to_string()
+  } else {
+    format!("{}.pdf", name)
+  }
+}
+
+// main typst source.  invoice data is read from json rather than pasted into
+// the source, so user text is never interpreted as typst markup.
+pub const INVOICE_MAIN: &str = "
+#import \"invoice.typ\": *
+
+#show: invoice.with(
+  data: json(\"invoice.json\"),
+  banner-image: none,
+  hourly-rate: 100,
+  tax: 0,
+  styling: ( font: none ), // Explicitly use Typst's default font
+)
+";
+
+// the fonts bundled with typst, loaded once.
+struct Fonts {
+  book: Prehashed<FontBook>,
+  fonts: Vec<Font>,
+}
+
+static FONTS: OnceLock<Fonts> = OnceLock::new();
+
+fn fonts() -> &'static Fonts {
+  FONTS.get_or_init(|| {
+    let fonts: Vec<Font> = typst_assets::fonts()
+      .flat_map(|data| Font::iter(Bytes::from_static(data)))
+      .collect();
+    Fonts {
+      book: Prehashed::new(FontBook::from_fonts(&fonts)),
+      fonts: fonts,
+    }
+  })
+}
+
+// everything typst needs to compile an invoice: the invoice source, the
+// template, the invoice data, and the fonts bundled with typst.  no
+// filesystem access.
+struct InvoiceWorld {
+  library: Prehashed<Library>,
+  fonts: &'static Fonts,
+  main: Source,
+  template: Source,
+  data_id: FileId,
+  data: Bytes,
+}
+
+impl InvoiceWorld {
+  fn new(data: Vec<u8>) -> InvoiceWorld {
+    InvoiceWorld {
+      library: Prehashed::new(Library::default()),
+      fonts: fonts(),
+      main: Source::new(
+        FileId::new(None, VirtualPath::new("main.typ")),
+        INVOICE_MAIN.to_string(),
+      ),
+      template: Source::new(
+        FileId::new(None, VirtualPath::new("invoice.typ")),
+        INVOICE_TEMPLATE.to_string(),
+      ),
+      data_id: FileId::new(None, VirtualPath::new("invoice.json")),
+      data: Bytes::from(data),
+    }
+  }
+}
+
+impl typst::World for InvoiceWorld {
+  fn library(&self) -> &Prehashed<Library> {
+    &self.library
+  }
+
+  fn book(&self) -> &Prehashed<FontBook> {
+    &self.fonts.book
+  }
+
+  fn main(&self) -> Source {
+    self.main.clone()
+  }
+
+  fn source(&self, id: FileId) -> FileResult<Source> {
+    if id == self.main.id() {
+      Ok(self.main.clone())
+    } else if id == self.template.id() {
+      Ok(self.template.clone())
+    } else {
+      Err(FileError::NotFound(id.vpath().as_rootless_path().into()))
+    }
+  }
+
+  fn file(&self, id: FileId) -> FileResult<Bytes> {
+    if id == self.data_id {
+      Ok(self.data.clone())
+    } else {
+      Ok(Bytes::from(self.source(id)?.text().as_bytes()))
+    }
+  }
+
+  fn font(&self, index: usize) -> Option<Font> {
+    self.fonts.fonts.get(index).cloned()
+  }
+
+  fn today(&self, offset: Option<i64>) -> Option<Datetime> {
+    let now = chrono::Utc::now() + chrono::Duration::hours(offset.unwrap_or(0));
+    Datetime::from_ymd(now.year(), now.month() as u8, now.day() as u8)
+  }
+}
+
+// compile an invoice to pdf bytes, given its json data.
+pub fn compile_pdf(data: Vec<u8>) -> Result<Vec<u8>, orgauth::error::Error> {
+  let world = InvoiceWorld::new(data);
+  let mut tracer = typst::eval::Tracer::new();
+  match typst::compile(&world, &mut tracer) {
+    Ok(document) => Ok(typst_pdf::pdf(&document, Smart::Auto, None)),
+    Err(errs) => Err(orgauth::error::Error::String(format!(
+      "typst err {}",
+      errs
+        .iter()
+        .map(|e| e.message.to_string())
+        .collect::<Vec<String>>()
+        .join("; ")
+    ))),
+  }
+}
+
+pub fn invoice_item(item: &InvoiceItem) -> serde_json::Value {
+  serde_json::json!({
+    "item": item.description,
+    "dur-min": 0,
+    "hours": item.duration,
+    "rate": item.rate,
+  })
+}
+
+pub fn run_invoice(print_invoice: PrintInvoice) -> Result<Vec<u8>, orgauth::error::Error> {
   let items = print_invoice
     .items
     .iter()
-    .map(|item| invoice_str(item))
-    .collect::<Vec<String>>()
-    .concat();
+    .map(|item| invoice_item(item))
+    .collect::<Vec<serde_json::Value>>();
 
   let eelines = print_invoice.payee.split('\n').count();
   let erlines = print_invoice.payer.split('\n').count();
@@ -95,84 +261,33 @@ pub fn run_invoice(print_invoice: PrintInvoice) -> Result<PathBuf, orgauth::erro
     ),
   };
 
-  let typ = format!(
-    "
-#import \"../invoice.typ\": *
-
-
-#let biller = \"{}\"
-#let recipient = \"{}\"
+  let data = serde_json::json!({
+    "language": "en",
+    "invoice-id": print_invoice.id,
+    "issuing-date": print_invoice.date,
+    "due-date": print_invoice.due_date,
+    "biller": payee,
+    "recipient": payer,
+    "currency-symbol": print_invoice.currency,
+    "items": items,
+    "extraFields": print_invoice
+      .extra_fields
+      .iter()
+      .map(|ef| vec![ef.n.clone(), ef.v.clone()])
+      .collect::<Vec<Vec<String>>>(),
+  });
 
+  compile_pdf(serde_json::to_vec(&data)?)
+}
 
-#let table-data = ( {} )
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-#show: invoice.with(
-  language: \"en\",
-  banner-image: none,
-  invoice-id: \"{}\",
-  // Set this to create a cancellation invoice
-  // cancellation-id: \"2024-03-24t210835\",
-  issuing-date: \"{}\",
-  due-date: {},
-  extraFields: {},
-  biller: biller,
-  hourly-rate: 100,
-  currency-symbol: \"{}\",
-  recipient: recipient,
-  tax: 0,
-  items: table-data,
-  styling: ( font: none ), // Explicitly use Typst's default font
-)",
-    payee,
-    payer,
-    items,
-    print_invoice.id,
-    print_invoice.date,
-    print_invoice
-      .due_date
-      .map(|dd| format!("\"{}\"", dd))
-      .unwrap_or("none".to_string()),
-    format!(
-      "( {} )",
-      print_invoice
-        .extra_fields
-        .iter()
-        .map(|ef| -> String { format!("(\"{}\", \"{}\"), ", ef.n, ef.v) })
-        .collect::<Vec<String>>()
-        .join("")
-    ),
-    print_invoice.currency
-  );
-
-  let invoicepath = format!("{}/{}{}", INVOICE_DIR, print_invoice.id, ".typ");
-  let invoicepdf = format!("{}/{}{}", INVOICE_DIR, print_invoice.id, ".pdf");
-
-  orgauth::util::write_string(invoicepath.as_str(), typ.as_str())?;
-
-  let mut child = Command::new("typst");
-  child
-    .arg("compile")
-    .arg(invoicepath.to_string())
-    .arg("--root")
-    .arg(".");
-
-  let mut res = child.spawn()?;
-
-  match res.wait() {
-    Ok(exit_code) => {
-      if exit_code.success() {
-        // add file to result.
-        Ok(invoicepdf.into())
-      } else {
-        Err(orgauth::error::Error::String(format!(
-          "typst err {:?}",
-          exit_code
-        )))
-      }
-    }
-    Err(e) => Err(orgauth::error::Error::String(format!(
-      "invoice err {:?}",
-      e
-    ))),
+  #[test]
+  fn pdf_filename_safe() {
+    assert_eq!(pdf_filename("INV-7.a"), "INV-7.a.pdf");
+    assert_eq!(pdf_filename("2024/01 \"x\""), "2024_01__x_.pdf");
+    assert_eq!(pdf_filename(""), "invoice.pdf");
   }
 }
@@ -1,9 +1,16 @@
+mod alerts;
+mod api;
 mod config;
+mod csvdata;
 mod data;
+mod distribution;
+mod forecast;
+mod ics;
 mod interfaces;
 mod invoice;
 mod messages;
 mod migrations;
+mod rates;
 mod sqldata;
 use actix_session::{
   config::PersistentSession, storage::CookieSessionStore, Session, SessionMiddleware,
@@ -246,6 +253,7 @@ fn defcon() -> Config {
     ip: "127.0.0.1".to_string(),
     port: 8000,
     static_path: None,
+    purge_deleted_days: Some(30),
     orgauth_config: oc,
   }
 }
@@ -268,14 +276,27 @@ async fn err_main() -> Result<(), Box<dyn Error>> {
     .version("1.0")
     .author("Ben Burdette")
     .about("team time clock web server")
-    // .arg(
-    //   Arg::with_name("export")
-    //     .short("e")
-    //     .long("export")
-    //     .value_name("FILE")
-    //     .help("Export database to json")
-    //     .takes_value(true),
-    // )
+    .arg(
+      Arg::with_name("export")
+        .short("e")
+        .long("export")
+        .value_name("FILE")
+        .help("Export database to json")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("import")
+        .short("i")
+        .long("import")
+        .value_name("FILE")
+        .help("Import projects from an exported json file")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("create_users")
+        .long("create_users")
+        .help("with --import, register users missing from the database"),
+    )
     .arg(
       Arg::with_name("config")
         .short("c")
@@ -323,21 +344,40 @@ async fn err_main() -> Result<(), Box<dyn Error>> {
     None => load_config("config.toml")?,
   };
 
+  // are we importing?
+  if let Some(importfile) = matches.value_of("import") {
+    sqldata::dbinit(
+      config.orgauth_config.db.as_path(),
+      config.orgauth_config.login_token_expiration_ms,
+    )?;
+
+    let export: data::Export = serde_json::from_str(util::load_string(importfile)?.as_str())?;
+    let report = sqldata::import_db(
+      config.orgauth_config.db.as_path(),
+      export,
+      matches.is_present("create_users"),
+    )?;
+
+    println!("imported from file: {}\n{:?}", importfile, report);
+    return Ok(());
+  }
+
   // are we exporting the DB?
   match matches.value_of("export") {
-    Some(_exportfile) => {
+    Some(exportfile) => {
       // do that exporting...
       sqldata::dbinit(
         config.orgauth_config.db.as_path(),
         config.orgauth_config.login_token_expiration_ms,
       )?;
 
-      error!("export is unimplemented!");
+      util::write_string(
+        exportfile,
+        serde_json::to_string_pretty(&sqldata::export_db(config.orgauth_config.db.as_path())?)?
+          .as_str(),
+      )?;
 
-      // util::write_string(
-      //   exportfile,
-      //   serde_json::to_string_pretty(&sqldata::export_db(config.db.as_path())?)?.as_str(),
-      // )?;
+      println!("database exported to file: {}", exportfile);
 
       Ok(())
     }
@@ -355,11 +395,6 @@ async fn err_main() -> Result<(), Box<dyn Error>> {
         }
       }
 
-      let id = PathBuf::from(invoice::INVOICE_DIR); // .to_string().into()?;
-      if !std::path::Path::exists(&id) {
-        std::fs::create_dir_all(&id)?;
-      }
-
       info!("config: {:?}", config);
 
       sqldata::dbinit(
@@ -378,6 +413,30 @@ async fn err_main() -> Result<(), Box<dyn Error>> {
         }
       });
 
+      let pdconfig = config.clone();
+
+      let _purgeguard = timer.schedule_repeating(chrono::Duration::days(1), move || {
+        if let Some(days) = pdconfig.purge_deleted_days {
+          match sqldata::connection_open(pdconfig.orgauth_config.db.as_path())
+            .and_then(|conn| sqldata::purge_deleted(&conn, days))
+          {
+            Err(e) => error!("purge_deleted error: {}", e),
+            Ok(_) => (),
+          }
+        }
+      });
+
+      let alconfig = config.clone();
+
+      let _alertguard =
+        timer.schedule_repeating(
+          chrono::Duration::hours(1),
+          move || match alerts::check_alerts(&alconfig) {
+            Err(e) => error!("check_alerts error: {}", e),
+            Ok(_) => (),
+          },
+        );
+
       // promoting a user to admin?
       if let Some(uid) = matches.value_of("promote_to_admin") {
         let conn = sqldata::connection_open(config.orgauth_config.db.as_path())?;
@@ -433,6 +492,35 @@ async fn err_main() -> Result<(), Box<dyn Error>> {
           .service(web::resource(r"/register/{uid}/{key}").route(web::get().to(register)))
           .service(web::resource(r"/newemail/{uid}/{token}").route(web::get().to(new_email)))
           .service(web::resource(r"/invoice").route(web::post().to(invoice::invoice)))
+          .service(web::resource("/api/v1/projects").route(web::get().to(api::projects)))
+          .service(web::resource(r"/api/v1/projects/{id}").route(web::get().to(api::project)))
+          .service(
+            web::resource(r"/api/v1/projects/{id}/time")
+              .route(web::get().to(api::project_time))
+              .route(web::post().to(api::save_project_time)),
+          )
+          .service(
+            web::resource(r"/api/v1/projects/{id}/distribution")
+              .route(web::get().to(api::distribution)),
+          )
+          .service(
+            web::resource(r"/api/v1/projects/{id}/forecast").route(web::get().to(api::forecast)),
+          )
+          .service(
+            web::resource(r"/api/v1/projects/{id}/invoices").route(web::get().to(api::invoices)),
+          )
+          .service(
+            web::resource(r"/api/v1/projects/{id}/clockin").route(web::post().to(api::clock_in)),
+          )
+          .service(
+            web::resource(r"/api/v1/projects/{id}/csv/{kind}")
+              .route(web::get().to(api::export_csv))
+              .route(web::post().to(api::import_csv)),
+          )
+          .service(web::resource("/api/v1/clockout").route(web::post().to(api::clock_out)))
+          .service(web::resource("/api/v1/timers").route(web::get().to(api::timers)))
+          .service(web::resource("/api/v1/time").route(web::get().to(api::user_time)))
+          .service(web::resource(r"/ics/{token}").route(web::get().to(ics::ics_feed)))
           .service(actix_files::Files::new("/static/", staticpath))
           .service(web::resource("/{tail:.*}").route(web::get().to(mainpage)))
       })
@@ -1039,3 +1039,599 @@ pub fn udpate12(dbfile: &Path) -> Result<(), orgauth::error::Error> {
 
   Ok(())
 }
+
+pub fn udpate13(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // distribution strategy, as json.  null means WorkedFirst.
+  m.change_table("project", |t| {
+    t.add_column("distribution", types::text().nullable(true));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate14(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // running timers.  one per user; clocking out turns it into a timeentry.
+  m.create_table("activetimer", |t| {
+    t.add_column(
+      "user",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column(
+      "project",
+      types::foreign(
+        "project",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column("description", types::text().nullable(false));
+    t.add_column("startdate", types::integer().nullable(false));
+    t.add_index("activetimerunq", types::index(vec!["user"]).unique(true));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate15(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // issued invoices.
+  m.create_table("invoice", |t| {
+    t.add_column(
+      "id",
+      types::integer()
+        .primary(true)
+        .increments(true)
+        .nullable(false),
+    );
+    t.add_column(
+      "project",
+      types::foreign(
+        "project",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column("number", types::text().nullable(false));
+    t.add_column("date", types::text().nullable(false));
+    t.add_column("due_date", types::text().nullable(true));
+    t.add_column("payer", types::text().nullable(false));
+    t.add_column("payee", types::text().nullable(false));
+    t.add_column("items", types::text().nullable(false));
+    t.add_column("extra_fields", types::text().nullable(false));
+    t.add_column("total", types::float().nullable(false));
+    t.add_column("currency", types::text().nullable(false));
+    t.add_column("status", types::text().nullable(false));
+    t.add_column("createdate", types::integer().nullable(false));
+    t.add_column("changeddate", types::integer().nullable(false));
+    t.add_column(
+      "creator",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_index(
+      "invoiceunq",
+      types::index(vec!["project", "number"]).unique(true),
+    );
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate16(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // time entries billed by each invoice.
+  m.create_table("invoicetimeentry", |t| {
+    t.add_column(
+      "invoice",
+      types::foreign(
+        "invoice",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column(
+      "timeentry",
+      types::foreign(
+        "timeentry",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_index(
+      "invoicetimeentryunq",
+      types::index(vec!["invoice", "timeentry"]).unique(true),
+    );
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate17(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // time entries before the lock date can't be edited.
+  m.change_table("project", |t| {
+    t.add_column("lockdate", types::integer().nullable(true));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate18(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // per member pay rate.  null means use the project rate.
+  m.change_table("projectmember", |t| {
+    t.add_column("rate", types::float().nullable(true));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate19(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // rate history.  user is null for the project rate.
+  m.create_table("projectrate", |t| {
+    t.add_column(
+      "id",
+      types::integer()
+        .primary(true)
+        .increments(true)
+        .nullable(false),
+    );
+    t.add_column(
+      "project",
+      types::foreign(
+        "project",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column(
+      "user",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(true),
+    );
+    t.add_column("rate", types::float().nullable(true));
+    t.add_column("effectivedate", types::integer().nullable(false));
+    t.add_column("createdate", types::integer().nullable(false));
+    t.add_column(
+      "creator",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  // current rates apply to all existing time.  the creator is the first
+  // project admin.
+  conn.execute(
+    "insert into projectrate (project, user, rate, effectivedate, createdate, creator)
+     select p.id, null, p.rate, 0, p.changeddate,
+       (select pm.user from projectmember pm where pm.project = p.id and pm.role = 'Admin')
+     from project p
+     where p.rate is not null and
+       exists (select * from projectmember pm where pm.project = p.id and pm.role = 'Admin')",
+    params![],
+  )?;
+  conn.execute(
+    "insert into projectrate (project, user, rate, effectivedate, createdate, creator)
+     select pm.project, pm.user, pm.rate, 0, p.changeddate,
+       (select a.user from projectmember a where a.project = p.id and a.role = 'Admin')
+     from projectmember pm, project p
+     where pm.project = p.id and pm.rate is not null and
+       exists (select * from projectmember a where a.project = p.id and a.role = 'Admin')",
+    params![],
+  )?;
+
+  Ok(())
+}
+
+pub fn udpate20(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // personal api tokens.  only a hash of the token is kept.
+  m.create_table("apitoken", |t| {
+    t.add_column(
+      "id",
+      types::integer()
+        .primary(true)
+        .increments(true)
+        .nullable(false),
+    );
+    t.add_column(
+      "user",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column("name", types::text().nullable(false));
+    t.add_column("tokenhash", types::text().nullable(false).unique(true));
+    t.add_column("createdate", types::integer().nullable(false));
+    t.add_column("lastused", types::integer().nullable(true));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate21(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // calendar feeds.  project is null for a user's feed over all projects.
+  m.create_table("icsfeed", |t| {
+    t.add_column(
+      "id",
+      types::integer()
+        .primary(true)
+        .increments(true)
+        .nullable(false),
+    );
+    t.add_column(
+      "user",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column(
+      "project",
+      types::foreign(
+        "project",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(true),
+    );
+    t.add_column("tokenhash", types::text().nullable(false).unique(true));
+    t.add_column("createdate", types::integer().nullable(false));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate22(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  m.create_table("projecttag", |t| {
+    t.add_column(
+      "id",
+      types::integer()
+        .primary(true)
+        .increments(true)
+        .nullable(false),
+    );
+    t.add_column(
+      "project",
+      types::foreign(
+        "project",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column("name", types::text().nullable(false));
+    t.add_index(
+      "projecttagunq",
+      types::index(vec!["project", "name"]).unique(true),
+    );
+  });
+
+  m.create_table("timeentrytag", |t| {
+    t.add_column(
+      "timeentry",
+      types::foreign(
+        "timeentry",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column(
+      "tag",
+      types::foreign(
+        "projecttag",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_index(
+      "timeentrytagunq",
+      types::index(vec!["timeentry", "tag"]).unique(true),
+    );
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate23(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // estimate is in millis, like other durations.
+  m.create_table("task", |t| {
+    t.add_column(
+      "id",
+      types::integer()
+        .primary(true)
+        .increments(true)
+        .nullable(false),
+    );
+    t.add_column(
+      "project",
+      types::foreign(
+        "project",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column("name", types::text().nullable(false));
+    t.add_column("estimate", types::integer().nullable(true));
+    t.add_column("status", types::text().nullable(false));
+    t.add_column(
+      "assignee",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(true),
+    );
+    t.add_column("createdate", types::integer().nullable(false));
+    t.add_column("changeddate", types::integer().nullable(false));
+    t.add_column(
+      "creator",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+  });
+
+  m.change_table("timeentry", |t| {
+    t.add_column(
+      "task",
+      types::foreign(
+        "task",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(true),
+    );
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate24(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // alert admins when remaining allocated millis drop to the threshold.
+  // alert_sent is set until remaining goes back above the threshold.
+  m.change_table("project", |t| {
+    t.add_column("alert_threshold", types::integer().nullable(true));
+    t.add_column("alert_sent", types::boolean().nullable(true));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate25(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // append only.  before and after are json, null for inserts and deletes.
+  m.create_table("audit", |t| {
+    t.add_column(
+      "id",
+      types::integer()
+        .primary(true)
+        .increments(true)
+        .nullable(false),
+    );
+    t.add_column(
+      "project",
+      types::foreign(
+        "project",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column(
+      "user",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column("tablename", types::text().nullable(false));
+    t.add_column("recordid", types::integer().nullable(false));
+    t.add_column("action", types::text().nullable(false));
+    t.add_column("before", types::text().nullable(true));
+    t.add_column("after", types::text().nullable(true));
+    t.add_column("changedate", types::integer().nullable(false));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate26(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // soft delete.  deleted rows are hidden, restorable, and purged later.
+  m.change_table("timeentry", |t| {
+    t.add_column("deleted", types::integer().nullable(true));
+  });
+  m.change_table("allocation", |t| {
+    t.add_column("deleted", types::integer().nullable(true));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  // deleted entries shouldn't hold their (user, startdate) slot.
+  conn.execute_batch(
+    "drop index timeentryunq;
+     create unique index timeentryunq on timeentry (user, startdate) where deleted is null;",
+  )?;
+
+  Ok(())
+}
+
+pub fn udpate27(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // reject overlapping time entries, rather than just warning about them.
+  m.change_table("project", |t| {
+    t.add_column("reject_overlaps", types::boolean().nullable(true));
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  Ok(())
+}
+
+pub fn udpate28(dbfile: &Path) -> Result<(), orgauth::error::Error> {
+  let conn = Connection::open(dbfile)?;
+  let mut m = Migration::new();
+
+  // low allocation alerts sent, per admin.  replaces project.alert_sent.
+  m.create_table("alertsent", |t| {
+    t.add_column(
+      "project",
+      types::foreign(
+        "project",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column(
+      "user",
+      types::foreign(
+        "orgauth_user",
+        "id",
+        types::ReferentialAction::Restrict,
+        types::ReferentialAction::Restrict,
+      )
+      .nullable(false),
+    );
+    t.add_column("sentdate", types::integer().nullable(false));
+    t.add_index(
+      "alertsentunq",
+      types::index(vec!["project", "user"]).unique(true),
+    );
+  });
+
+  conn.execute_batch(m.make::<Sqlite>().as_str())?;
+
+  // admins of projects already alerted have been sent one.
+  let now = orgauth::util::now()?;
+  conn.execute(
+    "insert into alertsent (project, user, sentdate)
+       select p.id, pm.user, ?1 from project p, projectmember pm where
+         p.alert_sent and pm.project = p.id and pm.role = 'Admin'",
+    params![now],
+  )?;
+
+  Ok(())
+}
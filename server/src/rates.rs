@@ -0,0 +1,68 @@
+use crate::data::ProjectRate;
+use crate::distribution::MILLIS_PER_DAY;
+use crate::sqldata;
+use rusqlite::Connection;
+use std::collections::HashMap;
+
+// hourly rates for a project.  members without their own rate get the
+// project rate.  'history' holds rate changes, oldest first; with no history
+// the current rates apply to all time.
+pub struct Rates {
+  pub project: Option<f64>,
+  pub members: HashMap<i64, f64>,
+  pub history: Vec<ProjectRate>,
+}
+
+impl Rates {
+  // project rate in force at 'date'.  dates before the first change get the
+  // earliest rate.
+  pub fn project_rate_at(&self, date: i64) -> Option<f64> {
+    let mut prs = self.history.iter().filter(|pr| pr.user.is_none());
+    match prs.clone().filter(|pr| pr.effectivedate <= date).last() {
+      Some(pr) => pr.rate,
+      None => match prs.next() {
+        Some(pr) => pr.rate,
+        None => self.project,
+      },
+    }
+  }
+
+  // member's own rate at 'date', if they had one.
+  pub fn member_rate_at(&self, user: i64, date: i64) -> Option<f64> {
+    let mut mrs = self.history.iter().filter(|pr| pr.user == Some(user));
+    match mrs.clone().filter(|pr| pr.effectivedate <= date).last() {
+      Some(pr) => pr.rate,
+      None => match mrs.next() {
+        Some(_) => None,
+        None => self.members.get(&user).copied(),
+      },
+    }
+  }
+
+  pub fn rate_at(&self, user: i64, date: i64) -> Option<f64> {
+    match self.member_rate_at(user, date) {
+      Some(r) => Some(r),
+      None => self.project_rate_at(date),
+    }
+  }
+
+  // rate for time worked on UTC 'day', as numbered by millis_per_day: the
+  // rate in force at the start of the day.  invoices and distributions both
+  // price time this way.
+  pub fn day_rate(&self, user: i64, day: i64) -> Option<f64> {
+    self.rate_at(user, day * MILLIS_PER_DAY)
+  }
+}
+
+pub fn project_rates(conn: &Connection, projectid: i64) -> Result<Rates, orgauth::error::Error> {
+  let project = sqldata::read_project(conn, projectid)?;
+  let members = sqldata::member_list(conn, projectid)?;
+  Ok(Rates {
+    project: project.rate,
+    members: members
+      .iter()
+      .filter_map(|m| m.rate.map(|r| (m.id, r)))
+      .collect(),
+    history: sqldata::project_rate_history(conn, projectid)?,
+  })
+}
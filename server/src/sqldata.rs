@@ -1,18 +1,28 @@
 use crate::data::{
-  Allocation, ListProject, PayEntry, PayType, Project, ProjectEdit, ProjectMember, ProjectTime,
-  Role, SaveAllocation, SavePayEntry, SaveProject, SaveProjectEdit, SaveProjectInvoice,
-  SaveProjectTime, SaveTimeEntry, SavedProject, SavedProjectEdit, TimeEntry, User, UserInviteData,
+  ActiveTimer, Allocation, ApiToken, AuditAction, AuditEntry, ClockIn, ClockOut, CreatedApiToken,
+  CreatedIcsFeed, DeletedEntries, Export, IcsFeed, ImportConflict, ImportReport, Invoice,
+  InvoiceItem, InvoiceStatus, InvoiceTime, InvoiceTimeEntry, ListProject, LockedTimeEntry,
+  NewApiToken, NewIcsFeed, Overlap, OverlapReport, PayEntry, PayType, PrintInvoice, Project,
+  ProjectEdit, ProjectExport, ProjectMember, ProjectRate, ProjectTime, ProjectTimeConflicts,
+  ProjectTimeError, RestoreEntries, Role, SaveAllocation, SavePayEntry, SaveProject,
+  SaveProjectEdit, SaveProjectInvoice, SaveProjectTime, SaveTask, SaveTimeEntry, SavedProject,
+  SavedProjectEdit, SetProjectRate, TagHours, TagReport, Task, TaskHours, TaskReport, TaskStatus,
+  TimeEntry, TimeEntryCheck, TimeEntryIssue, User, UserInviteData, EXPORT_VERSION,
 };
+use crate::distribution::{millis_per_day, MILLIS_PER_DAY, MILLIS_PER_HOUR};
 use crate::migrations as tm;
+use crate::rates;
 use barrel::backend::Sqlite;
 use log::info;
 use orgauth::data::RegistrationData;
 use orgauth::endpoints::Callbacks;
 use orgauth::util::now;
 use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 use std::str::FromStr;
 use std::time::Duration;
+use uuid::Uuid;
 
 pub fn timeclonk_callbacks() -> Callbacks {
   Callbacks {
@@ -33,7 +43,7 @@ pub fn on_new_user(
     Some(d) => {
       let invitedata: UserInviteData = serde_json::from_str(d.as_str())?;
       match creator {
-        Some(cuid) => {
+        Some(cuid) => all_or_nothing(conn, || {
           for p in invitedata.projects {
             match member_role(conn, cuid, p.id)? {
               Some(Role::Admin) => {
@@ -48,11 +58,10 @@ pub fn on_new_user(
               None => (),
             }
           }
-        }
-        None => (),
+          Ok(())
+        })?,
+        None => Ok(()),
       }
-
-      Ok(())
     }
     None => Ok(()),
   }
@@ -199,6 +208,86 @@ pub fn dbinit(
     tm::udpate12(&dbfile)?;
     set_single_value(&conn, "migration_level", "12")?;
   }
+  if nlevel < 13 {
+    info!("udpate13");
+    tm::udpate13(&dbfile)?;
+    set_single_value(&conn, "migration_level", "13")?;
+  }
+  if nlevel < 14 {
+    info!("udpate14");
+    tm::udpate14(&dbfile)?;
+    set_single_value(&conn, "migration_level", "14")?;
+  }
+  if nlevel < 15 {
+    info!("udpate15");
+    tm::udpate15(&dbfile)?;
+    set_single_value(&conn, "migration_level", "15")?;
+  }
+  if nlevel < 16 {
+    info!("udpate16");
+    tm::udpate16(&dbfile)?;
+    set_single_value(&conn, "migration_level", "16")?;
+  }
+  if nlevel < 17 {
+    info!("udpate17");
+    tm::udpate17(&dbfile)?;
+    set_single_value(&conn, "migration_level", "17")?;
+  }
+  if nlevel < 18 {
+    info!("udpate18");
+    tm::udpate18(&dbfile)?;
+    set_single_value(&conn, "migration_level", "18")?;
+  }
+  if nlevel < 19 {
+    info!("udpate19");
+    tm::udpate19(&dbfile)?;
+    set_single_value(&conn, "migration_level", "19")?;
+  }
+  if nlevel < 20 {
+    info!("udpate20");
+    tm::udpate20(&dbfile)?;
+    set_single_value(&conn, "migration_level", "20")?;
+  }
+  if nlevel < 21 {
+    info!("udpate21");
+    tm::udpate21(&dbfile)?;
+    set_single_value(&conn, "migration_level", "21")?;
+  }
+  if nlevel < 22 {
+    info!("udpate22");
+    tm::udpate22(&dbfile)?;
+    set_single_value(&conn, "migration_level", "22")?;
+  }
+  if nlevel < 23 {
+    info!("udpate23");
+    tm::udpate23(&dbfile)?;
+    set_single_value(&conn, "migration_level", "23")?;
+  }
+  if nlevel < 24 {
+    info!("udpate24");
+    tm::udpate24(&dbfile)?;
+    set_single_value(&conn, "migration_level", "24")?;
+  }
+  if nlevel < 25 {
+    info!("udpate25");
+    tm::udpate25(&dbfile)?;
+    set_single_value(&conn, "migration_level", "25")?;
+  }
+  if nlevel < 26 {
+    info!("udpate26");
+    tm::udpate26(&dbfile)?;
+    set_single_value(&conn, "migration_level", "26")?;
+  }
+  if nlevel < 27 {
+    info!("udpate27");
+    tm::udpate27(&dbfile)?;
+    set_single_value(&conn, "migration_level", "27")?;
+  }
+  if nlevel < 28 {
+    info!("udpate28");
+    tm::udpate28(&dbfile)?;
+    set_single_value(&conn, "migration_level", "28")?;
+  }
 
   info!("db up to date.");
 
@@ -237,7 +326,7 @@ pub fn project_list(
   // projects ordered by last clonk.
   let mut pstmt = conn.prepare(
     "select project.id, project.name, projectmember.role from project, projectmember,
-      (select project, max(startdate) as sd from timeentry where user = ?1 group by project) te
+      (select project, max(startdate) as sd from timeentry where user = ?1 and deleted is null group by project) te
     where
     project.id = projectmember.project and
     te.project = project.id and
@@ -266,7 +355,7 @@ pub fn project_list(
     where
     project.id = projectmember.project and
     projectmember.user = ?1 and
-    not exists (select * from timeentry where project = project.id and user = ?1)
+    not exists (select * from timeentry where project = project.id and user = ?1 and deleted is null)
     ",
   )?;
   let mut rempty: Vec<ListProject> = pstmt
@@ -293,23 +382,49 @@ pub fn save_project_edit(
   conn: &Connection,
   user: i64,
   project_edit: SaveProjectEdit,
+) -> Result<SavedProjectEdit, orgauth::error::Error> {
+  all_or_nothing(conn, || save_project_edit_inner(conn, user, project_edit))?
+}
+
+fn save_project_edit_inner(
+  conn: &Connection,
+  user: i64,
+  project_edit: SaveProjectEdit,
 ) -> Result<SavedProjectEdit, orgauth::error::Error> {
   let sp = save_project(conn, user, project_edit.project)?;
+  let now = now()?;
+  let oldrates: HashMap<i64, Option<f64>> = member_list(conn, sp.id)?
+    .into_iter()
+    .map(|m| (m.id, m.rate))
+    .collect();
 
   for m in project_edit.members {
     if m.delete {
       conn.execute(
         "delete from projectmember
          where user = ?1 and project = ?2",
-        params![sp.id, m.id],
+        params![m.id, sp.id],
       )?;
     } else {
       conn.execute(
-        "insert into projectmember (project, user, role)
-         values (?1, ?2, ?3)
-         on conflict (project, user) do update set role = ?3",
-        params![sp.id, m.id, m.role.to_string().as_str()],
+        "insert into projectmember (project, user, role, rate)
+         values (?1, ?2, ?3, ?4)
+         on conflict (project, user) do update set role = ?3,
+           rate = case when ?5 then null else coalesce(?4, rate) end",
+        params![
+          sp.id,
+          m.id,
+          m.role.to_string().as_str(),
+          m.rate,
+          m.clear_rate
+        ],
       )?;
+      // rate changes apply from now on.
+      let old = oldrates.get(&m.id).cloned().flatten();
+      let rate = if m.clear_rate { None } else { m.rate.or(old) };
+      if rate != old {
+        record_rate(conn, user, sp.id, Some(m.id), rate, now)?;
+      }
     }
   }
 
@@ -351,9 +466,16 @@ pub fn save_project(
   project: SaveProject,
 ) -> Result<SavedProject, orgauth::error::Error> {
   let now = now()?;
+  let distribution = match project.distribution {
+    Some(d) => Some(serde_json::to_value(d)?.to_string()),
+    None => None,
+  };
+
+  let rate = project.rate.map(|r| r as f64);
 
   let proj = match project.id {
     Some(id) => {
+      let oldrate = read_project(conn, id)?.rate;
       conn.execute(
         "update project set name = ?1,
                             description = ?2,
@@ -367,8 +489,9 @@ pub fn save_project(
                             public = ?10,
                             rate = ?11,
                             currency = ?12,
-                            changeddate = ?13
-          where id = ?14",
+                            distribution = coalesce(?13, distribution),
+                            changeddate = ?14
+          where id = ?15",
         params![
           project.name,
           project.description,
@@ -382,10 +505,15 @@ pub fn save_project(
           project.public,
           project.rate,
           project.currency,
+          distribution,
           now,
           id
         ],
       )?;
+      // rate changes apply from now on.
+      if oldrate != rate {
+        record_rate(conn, user, id, None, rate, now)?;
+      }
       SavedProject {
         id: id,
         changeddate: now,
@@ -393,8 +521,8 @@ pub fn save_project(
     }
     None => {
       conn.execute(
-        "insert into project (name, description, due_days, extra_fields, invoice_id_template, invoice_seq, payer, payee, generic_task, public, rate, currency, createdate, changeddate)
-         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        "insert into project (name, description, due_days, extra_fields, invoice_id_template, invoice_seq, payer, payee, generic_task, public, rate, currency, distribution, createdate, changeddate)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
         params![
           project.name,
           project.description,
@@ -408,6 +536,7 @@ pub fn save_project(
           project.public,
           project.rate,
           project.currency,
+          distribution,
           now,
           now
         ],
@@ -418,6 +547,9 @@ pub fn save_project(
          values (?1, ?2, 'Admin')",
         params![id, user],
       )?;
+      if rate != None {
+        record_rate(conn, user, id, None, rate, now)?;
+      }
       SavedProject {
         id: id,
         changeddate: now,
@@ -442,6 +574,10 @@ pub fn read_project(conn: &Connection, projectid: i64) -> Result<Project, orgaut
             project.public,
             project.rate,
             project.currency,
+            project.distribution,
+            project.lockdate,
+            project.alert_threshold,
+            coalesce(project.reject_overlaps, 0),
             project.createdate,
             project.changeddate
       from project, projectmember where
@@ -468,8 +604,18 @@ pub fn read_project(conn: &Connection, projectid: i64) -> Result<Project, orgaut
       public: row.get(10)?,
       rate: row.get(11)?,
       currency: row.get(12)?,
-      createdate: row.get(13)?,
-      changeddate: row.get(14)?,
+      // projects from before distributions were stored have none.
+      distribution: match row.get::<usize, Option<String>>(13)? {
+        Some(d) => serde_json::from_str(d.as_str()).map_err(|e| {
+          rusqlite::Error::FromSqlConversionFailure(13, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        None => Default::default(),
+      },
+      lockdate: row.get(14)?,
+      alert_threshold: row.get(15)?,
+      reject_overlaps: row.get(16)?,
+      createdate: row.get(17)?,
+      changeddate: row.get(18)?,
     })
   })?);
   r
@@ -480,7 +626,7 @@ pub fn member_list(
   projectid: i64,
 ) -> Result<Vec<ProjectMember>, orgauth::error::Error> {
   let mut pstmt = conn.prepare(
-        "select orgauth_user.id, orgauth_user.name, projectmember.role from orgauth_user, projectmember where
+        "select orgauth_user.id, orgauth_user.name, projectmember.role, projectmember.rate from orgauth_user, projectmember where
           orgauth_user.id = projectmember.user and
           projectmember.project = ?1",
       )?;
@@ -491,6 +637,7 @@ pub fn member_list(
           id: row.get(0)?,
           name: row.get(1)?,
           role: role,
+          rate: row.get(3)?,
         }),
         Err(_) => {
           // TODO this is a misuse of the rusqlite error.
@@ -539,32 +686,87 @@ pub fn read_project_edit(
   })
 }
 
+// billed: the entry is on an invoice that hasn't been voided.
+fn time_entry_from_row(row: &rusqlite::Row) -> Result<TimeEntry, rusqlite::Error> {
+  Ok(TimeEntry {
+    id: row.get(0)?,
+    project: row.get(1)?,
+    user: row.get(2)?,
+    description: row.get(3)?,
+    startdate: row.get(4)?,
+    enddate: row.get(5)?,
+    ignore: row.get(6)?,
+    createdate: row.get(7)?,
+    changeddate: row.get(8)?,
+    creator: row.get(9)?,
+    billed: row.get(10)?,
+    tags: Vec::new(),
+    task: row.get(11)?,
+  })
+}
+
+// fill in tags for time entries matching 'filter', a condition on 'te'.
+fn add_tags(
+  conn: &Connection,
+  mut timeentries: Vec<TimeEntry>,
+  filter: &str,
+  param: i64,
+) -> Result<Vec<TimeEntry>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    format!(
+      "select tt.timeentry, t.name from timeentrytag tt, projecttag t, timeentry te where
+          tt.tag = t.id and tt.timeentry = te.id and {}
+          order by t.name",
+      filter
+    )
+    .as_str(),
+  )?;
+  let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+  for r in pstmt.query_map(params![param], |row| Ok((row.get(0)?, row.get(1)?)))? {
+    let (te, name): (i64, String) = r?;
+    tags.entry(te).or_insert_with(Vec::new).push(name);
+  }
+  for te in timeentries.iter_mut() {
+    if let Some(t) = tags.remove(&te.id) {
+      te.tags = t;
+    }
+  }
+  Ok(timeentries)
+}
+
 pub fn user_time(conn: &Connection, userid: i64) -> Result<Vec<TimeEntry>, orgauth::error::Error> {
   let mut pstmt = conn.prepare(
-    "select te.id, te.project, te.user, te.description, te.startdate, te.enddate, te.ignore, te.createdate, te.changeddate, te.creator
+    "select te.id, te.project, te.user, te.description, te.startdate, te.enddate, te.ignore, te.createdate, te.changeddate, te.creator,
+            exists (select * from invoicetimeentry ite, invoice i where
+              ite.timeentry = te.id and ite.invoice = i.id and i.status != 'Void'),
+            te.task
           from timeentry te where
-    te.user = ?1",
+    te.user = ?1 and te.deleted is null",
   )?;
-  let r = Ok(
-    pstmt
-      .query_map(params![userid], |row| {
-        Ok(TimeEntry {
-          id: row.get(0)?,
-          project: row.get(1)?,
-          user: row.get(2)?,
-          description: row.get(3)?,
-          startdate: row.get(4)?,
-          enddate: row.get(5)?,
-          ignore: row.get(6)?,
-          createdate: row.get(7)?,
-          changeddate: row.get(8)?,
-          creator: row.get(9)?,
-        })
-      })?
-      .filter_map(|x| x.ok())
-      .collect(),
-  );
-  r
+  let r: Vec<TimeEntry> = pstmt
+    .query_map(params![userid], |row| time_entry_from_row(row))?
+    .filter_map(|x| x.ok())
+    .collect();
+  add_tags(conn, r, "te.user = ?1", userid)
+}
+
+// the user's time in projects they're still a member of.
+pub fn member_time(
+  conn: &Connection,
+  userid: i64,
+) -> Result<Vec<TimeEntry>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select te.id, te.project, te.user, te.description, te.startdate, te.enddate, te.ignore, te.createdate, te.changeddate, te.creator,
+            exists (select * from invoicetimeentry ite, invoice i where
+              ite.timeentry = te.id and ite.invoice = i.id and i.status != 'Void'),
+            te.task
+          from timeentry te, projectmember pm where
+    te.user = ?1 and te.deleted is null and pm.project = te.project and pm.user = te.user",
+  )?;
+  let r = pstmt
+    .query_map(params![userid], |row| time_entry_from_row(row))?
+    .collect::<Result<Vec<TimeEntry>, rusqlite::Error>>()?;
+  add_tags(conn, r, "te.user = ?1", userid)
 }
 
 pub fn time_entries(
@@ -572,30 +774,18 @@ pub fn time_entries(
   projectid: i64,
 ) -> Result<Vec<TimeEntry>, orgauth::error::Error> {
   let mut pstmt = conn.prepare(
-    "select te.id, te.project, te.user, te.description, te.startdate, te.enddate, te.ignore, te.createdate, te.changeddate, te.creator
+    "select te.id, te.project, te.user, te.description, te.startdate, te.enddate, te.ignore, te.createdate, te.changeddate, te.creator,
+            exists (select * from invoicetimeentry ite, invoice i where
+              ite.timeentry = te.id and ite.invoice = i.id and i.status != 'Void'),
+            te.task
           from timeentry te where
-    te.project = ?1",
+    te.project = ?1 and te.deleted is null",
   )?;
-  let r = Ok(
-    pstmt
-      .query_map(params![projectid], |row| {
-        Ok(TimeEntry {
-          id: row.get(0)?,
-          project: row.get(1)?,
-          user: row.get(2)?,
-          description: row.get(3)?,
-          startdate: row.get(4)?,
-          enddate: row.get(5)?,
-          ignore: row.get(6)?,
-          createdate: row.get(7)?,
-          changeddate: row.get(8)?,
-          creator: row.get(9)?,
-        })
-      })?
-      .filter_map(|x| x.ok())
-      .collect(),
-  );
-  r
+  let r: Vec<TimeEntry> = pstmt
+    .query_map(params![projectid], |row| time_entry_from_row(row))?
+    .filter_map(|x| x.ok())
+    .collect();
+  add_tags(conn, r, "te.project = ?1", projectid)
 }
 
 pub fn pay_entries(
@@ -609,33 +799,45 @@ pub fn pay_entries(
   )?;
   let r = Ok(
     pstmt
-      .query_map(params![projectid], |row| {
-        Ok(PayEntry {
-          id: row.get(0)?,
-          project: row.get(1)?,
-          user: row.get(2)?,
-          duration: row.get(3)?,
-          paytype: {
-            let pt: i64 = row.get(4)?;
-            if pt == 0 {
-              PayType::Invoiced
-            } else {
-              PayType::Paid
-            }
-          },
-          paymentdate: row.get(5)?,
-          description: row.get(6)?,
-          createdate: row.get(7)?,
-          changeddate: row.get(8)?,
-          creator: row.get(9)?,
-        })
-      })?
+      .query_map(params![projectid], |row| pay_entry_from_row(row))?
       .filter_map(|x| x.ok())
       .collect(),
   );
   r
 }
 
+fn pay_entry_from_row(row: &rusqlite::Row) -> Result<PayEntry, rusqlite::Error> {
+  Ok(PayEntry {
+    id: row.get(0)?,
+    project: row.get(1)?,
+    user: row.get(2)?,
+    duration: row.get(3)?,
+    paytype: {
+      let pt: i64 = row.get(4)?;
+      if pt == 0 {
+        PayType::Invoiced
+      } else {
+        PayType::Paid
+      }
+    },
+    paymentdate: row.get(5)?,
+    description: row.get(6)?,
+    createdate: row.get(7)?,
+    changeddate: row.get(8)?,
+    creator: row.get(9)?,
+  })
+}
+
+pub fn read_pay_entry(conn: &Connection, id: i64) -> Result<PayEntry, orgauth::error::Error> {
+  Ok(conn.query_row(
+    "select  pe.id, pe.project, pe.user, pe.duration, pe.type, pe.paymentdate, pe.description, pe.createdate, pe.changeddate, pe.creator
+          from payentry pe where
+          pe.id = ?1",
+    params![id],
+    |row| pay_entry_from_row(row),
+  )?)
+}
+
 pub fn save_pay_entry(
   conn: &Connection,
   uid: i64,
@@ -646,10 +848,14 @@ pub fn save_pay_entry(
     PayType::Invoiced => 0,
     PayType::Paid => 1,
   };
-  match spe.id {
-    Some(id) =>
-        conn.execute(
-          "update payentry set
+  let before = match spe.id {
+    Some(id) => Some(read_pay_entry(conn, id)?),
+    None => None,
+  };
+  let id = match spe.id {
+    Some(id) => {
+      conn.execute(
+        "update payentry set
               project =?1
             , user =?2
             , description =?3
@@ -658,26 +864,58 @@ pub fn save_pay_entry(
             , paymentdate =?6
             , changeddate =?7
               where id = ?8 ",
-          params![spe.project, spe.user, spe.description, spe.duration,  pt, spe.paymentdate, now, id],
-        )?,
-    None =>
+        params![
+          spe.project,
+          spe.user,
+          spe.description,
+          spe.duration,
+          pt,
+          spe.paymentdate,
+          now,
+          id
+        ],
+      )?;
+      id
+    }
+    None => {
       conn.execute(
         "insert into payentry (project, user, description, duration, type, paymentdate, createdate, changeddate, creator)
          values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
         params![spe.project, spe.user, spe.description, spe.duration, pt, spe.paymentdate, now, now, uid],
-      )?,
+      )?;
+      conn.last_insert_rowid()
+    }
   };
-  let id = conn.last_insert_rowid();
+  let after = read_pay_entry(conn, id)?;
+  record_audit(
+    conn,
+    uid,
+    after.project,
+    "payentry",
+    id,
+    before.as_ref(),
+    Some(&after),
+  )?;
   Ok(id)
 }
 
 // check for user membership before calling!
 pub fn delete_pay_entry(
   conn: &Connection,
-  _uid: i64,
+  uid: i64,
   peid: i64,
 ) -> Result<(), orgauth::error::Error> {
+  let before = read_pay_entry(conn, peid)?;
   conn.execute("delete from payentry where id = ?1", params![peid])?;
+  record_audit::<PayEntry>(
+    conn,
+    uid,
+    before.project,
+    "payentry",
+    peid,
+    Some(&before),
+    None,
+  )?;
   Ok(())
 }
 
@@ -687,68 +925,127 @@ pub fn allocations(
 ) -> Result<Vec<Allocation>, orgauth::error::Error> {
   let mut pstmt = conn.prepare(
     "select  a.id, a.project, a.duration, a.allocationdate, a.description, a.createdate, a.changeddate, a.creator
-          from allocation a, projectmember pm where
-          a.project = ?1",
+          from allocation a where
+          a.project = ?1 and a.deleted is null",
   )?;
   let r = Ok(
     pstmt
-      .query_map(params![projectid], |row| {
-        Ok(Allocation {
-          id: row.get(0)?,
-          project: row.get(1)?,
-          duration: row.get(2)?,
-          allocationdate: row.get(3)?,
-          description: row.get(4)?,
-          createdate: row.get(5)?,
-          changeddate: row.get(6)?,
-          creator: row.get(7)?,
-        })
-      })?
+      .query_map(params![projectid], |row| allocation_from_row(row))?
       .filter_map(|x| x.ok())
       .collect(),
   );
   r
 }
 
+fn allocation_from_row(row: &rusqlite::Row) -> Result<Allocation, rusqlite::Error> {
+  Ok(Allocation {
+    id: row.get(0)?,
+    project: row.get(1)?,
+    duration: row.get(2)?,
+    allocationdate: row.get(3)?,
+    description: row.get(4)?,
+    createdate: row.get(5)?,
+    changeddate: row.get(6)?,
+    creator: row.get(7)?,
+  })
+}
+
+pub fn read_allocation(conn: &Connection, id: i64) -> Result<Allocation, orgauth::error::Error> {
+  Ok(conn.query_row(
+    "select  a.id, a.project, a.duration, a.allocationdate, a.description, a.createdate, a.changeddate, a.creator
+          from allocation a where
+          a.id = ?1",
+    params![id],
+    |row| allocation_from_row(row),
+  )?)
+}
+
 pub fn save_allocation(
   conn: &Connection,
   uid: i64,
   sa: SaveAllocation,
 ) -> Result<i64, orgauth::error::Error> {
   let now = now()?;
-  match sa.id {
-    Some(id) =>
-        conn.execute(
-          "update allocation set
+  let before = match sa.id {
+    Some(id) => Some(read_allocation(conn, id)?),
+    None => None,
+  };
+  let id = match sa.id {
+    Some(id) => {
+      conn.execute(
+        "update allocation set
               project =?1
             , description =?2
             , duration =?3
             , allocationdate =?4
             , changeddate =?5
               where id = ?6 ",
-          params![sa.project, sa.description, sa.duration, sa.allocationdate, now, id],
-        )?,
-    None =>
+        params![
+          sa.project,
+          sa.description,
+          sa.duration,
+          sa.allocationdate,
+          now,
+          id
+        ],
+      )?;
+      id
+    }
+    None => {
       conn.execute(
         "insert into allocation (project, description, duration, allocationdate, createdate, changeddate, creator)
          values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
         params![sa.project, sa.description, sa.duration, sa.allocationdate, now, now, uid],
-      )?,
+      )?;
+      conn.last_insert_rowid()
+    }
   };
-  let id = conn.last_insert_rowid();
+  let after = read_allocation(conn, id)?;
+  record_audit(
+    conn,
+    uid,
+    after.project,
+    "allocation",
+    id,
+    before.as_ref(),
+    Some(&after),
+  )?;
   Ok(id)
 }
 
 // check for user membership before calling!
 pub fn delete_allocation(
   conn: &Connection,
-  _uid: i64,
+  uid: i64,
   id: i64,
 ) -> Result<(), orgauth::error::Error> {
-  conn.execute("delete from allocation where id = ?1", params![id])?;
+  let now = now()?;
+  let before = read_allocation(conn, id)?;
+  conn.execute(
+    "update allocation set deleted = ?1, changeddate = ?1 where id = ?2",
+    params![now, id],
+  )?;
+  record_audit::<Allocation>(
+    conn,
+    uid,
+    before.project,
+    "allocation",
+    id,
+    Some(&before),
+    None,
+  )?;
   Ok(())
 }
 
+// admins and members can change a project's time; observers can't.
+fn time_editor(conn: &Connection, uid: i64, projectid: i64) -> Result<bool, orgauth::error::Error> {
+  Ok(match member_role(conn, uid, projectid)? {
+    Some(Role::Admin) => true,
+    Some(Role::Member) => true,
+    _ => false,
+  })
+}
+
 pub fn is_project_member(
   conn: &Connection,
   uid: i64,
@@ -774,82 +1071,3307 @@ pub fn read_project_time(
   let timeentries = time_entries(conn, projectid)?;
   let payentries = pay_entries(conn, projectid)?;
   let allocations = allocations(conn, projectid)?;
+  let tasks = tasks(conn, projectid)?;
   Ok(ProjectTime {
     project: proj,
     members: members,
     timeentries: timeentries,
     payentries: payentries,
     allocations: allocations,
+    tasks: tasks,
+    warnings: Vec::new(),
   })
 }
 
-pub fn save_time_entry(
+pub fn project_ids(conn: &Connection) -> Result<Vec<i64>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare("select id from project order by id")?;
+  let r = pstmt
+    .query_map(params![], |row| Ok(row.get(0)?))?
+    .collect::<Result<Vec<i64>, rusqlite::Error>>()?;
+  Ok(r)
+}
+
+// everything, for backups and moving between hosts.
+pub fn export_db(dbfile: &Path) -> Result<Export, orgauth::error::Error> {
+  let conn = connection_open(dbfile)?;
+  let users = user_list(&conn)?;
+  let mut projects = Vec::new();
+  for pid in project_ids(&conn)? {
+    let mut pstmt = conn.prepare(
+      "select ite.invoice, ite.timeentry from invoicetimeentry ite, invoice i where
+         ite.invoice = i.id and i.project = ?1",
+    )?;
+    let links = pstmt
+      .query_map(params![pid], |row| {
+        Ok(InvoiceTimeEntry {
+          invoice: row.get(0)?,
+          timeentry: row.get(1)?,
+        })
+      })?
+      .collect::<Result<Vec<InvoiceTimeEntry>, rusqlite::Error>>()?;
+    let deleted = deleted_entries(&conn, pid)?;
+    projects.push(ProjectExport {
+      time: read_project_time(&conn, pid)?,
+      rates: project_rate_history(&conn, pid)?,
+      invoices: invoices(&conn, pid)?,
+      invoicetimeentries: links,
+      deletedtimeentries: deleted.timeentries,
+      deletedallocations: deleted.allocations,
+    });
+  }
+  Ok(Export {
+    version: EXPORT_VERSION,
+    users: users,
+    projects: projects,
+  })
+}
+
+// map user ids in the export to user ids in this db, by user name.
+fn import_uid(
   conn: &Connection,
+  export: &Export,
+  uidmap: &mut HashMap<i64, i64>,
   uid: i64,
-  spt: SaveTimeEntry,
 ) -> Result<i64, orgauth::error::Error> {
-  let now = now()?;
-  match spt.id {
-    Some(id) =>
-      conn.execute(
-        "update timeentry set
-            project =?1,
-            user = ?2,
-            description = ?3,
-            startdate = ?4,
-            enddate = ?5,
-            ignore = ?6,
-            changeddate = ?7
-          where id = ?8",
-        params![spt.project, spt.user, spt.description, spt.startdate, spt.enddate, spt.ignore, now, id],
-      )?,
-    None =>
-      conn.execute(
-        "insert into timeentry (project, user, description, startdate, enddate, ignore, createdate, changeddate, creator)
-         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        params![spt.project, spt.user, spt.description, spt.startdate, spt.enddate, spt.ignore, now, now, uid],
-      )?,
+  if let Some(id) = uidmap.get(&uid) {
+    return Ok(*id);
+  }
+  let name = match export.users.iter().find(|u| u.id == uid) {
+    Some(u) => u.name.clone(),
+    None => return Err(format!("user id {} not in export", uid).into()),
   };
-  let id = conn.last_insert_rowid();
-  Ok(id)
+  match orgauth::dbfun::read_user_by_name(conn, name.as_str()) {
+    Ok(user) => {
+      uidmap.insert(uid, user.id);
+      Ok(user.id)
+    }
+    Err(_) => Err(
+      format!(
+        "user '{}' not found; register them before importing, or import with --create_users",
+        name
+      )
+      .into(),
+    ),
+  }
 }
 
-// check for user membership before calling!
-pub fn delete_time_entry(
+// the project in this db that an exported project was imported into before,
+// if any.  projects keep their createdate on import, so name and createdate
+// find them.
+fn imported_project(
   conn: &Connection,
-  _uid: i64,
-  teid: i64,
+  name: &str,
+  createdate: i64,
+) -> Result<Option<i64>, orgauth::error::Error> {
+  match conn.query_row(
+    "select id from project where name = ?1 and createdate = ?2 order by id limit 1",
+    params![name, createdate],
+    |row| row.get(0),
+  ) {
+    Ok(id) => Ok(Some(id)),
+    Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+    Err(e) => Err(e.into()),
+  }
+}
+
+// recreate projects from an export.  projects already imported are matched by
+// name and createdate, and rows already in them are skipped.  rows that clash
+// with different rows in the db are reported as conflicts.  all or nothing -
+// on error, nothing is imported.
+// register the export's users that aren't in this db, with a random
+// password.  returns their names.
+fn import_users(conn: &Connection, export: &Export) -> Result<Vec<String>, orgauth::error::Error> {
+  let mut cb = timeclonk_callbacks();
+  let mut names = Vec::new();
+  for u in export.users.iter() {
+    if orgauth::dbfun::read_user_by_name(conn, u.name.as_str()).is_err() {
+      let rd = RegistrationData {
+        uid: u.name.clone(),
+        pwd: Uuid::new_v4().to_string(),
+        email: "".to_string(),
+      };
+      orgauth::dbfun::new_user(conn, &rd, None, None, false, None, &mut cb.on_new_user)?;
+      names.push(u.name.clone());
+    }
+  }
+  Ok(names)
+}
+
+// imported rows are audited as imports, by their creator.  deleted rows get
+// a delete after.
+fn audit_import<T: serde::Serialize>(
+  conn: &Connection,
+  creator: i64,
+  project: i64,
+  tablename: &str,
+  recordid: i64,
+  after: &T,
+  deleted: bool,
 ) -> Result<(), orgauth::error::Error> {
-  conn.execute("delete from timeentry where id = ?1", params![teid])?;
+  insert_audit(
+    conn,
+    creator,
+    project,
+    tablename,
+    recordid,
+    AuditAction::Import,
+    None,
+    Some(after),
+  )?;
+  if deleted {
+    insert_audit(
+      conn,
+      creator,
+      project,
+      tablename,
+      recordid,
+      AuditAction::Delete,
+      Some(after),
+      None,
+    )?;
+  }
   Ok(())
 }
 
-pub fn save_project_time(
-  conn: &Connection,
-  uid: i64,
-  spt: SaveProjectTime,
-) -> Result<ProjectTime, orgauth::error::Error> {
-  // is user a member of this project?
-  if is_project_member(conn, uid, spt.project)? {
-    for te in spt.savetimeentries {
-      save_time_entry(conn, uid, te)?;
+// with 'create_users', users missing from this db are registered first.
+pub fn import_db(
+  dbfile: &Path,
+  export: Export,
+  create_users: bool,
+) -> Result<ImportReport, orgauth::error::Error> {
+  if export.version < 1 || export.version > EXPORT_VERSION {
+    return Err(
+      format!(
+        "unknown export version {}; this server reads versions 1 to {}",
+        export.version, EXPORT_VERSION
+      )
+      .into(),
+    );
+  }
+
+  let mut conn = connection_open(dbfile)?;
+  let tx = conn.transaction()?;
+  let mut uidmap = HashMap::new();
+  let newusers = if create_users {
+    import_users(&tx, &export)?
+  } else {
+    Vec::new()
+  };
+  let mut report = ImportReport {
+    newusers: newusers,
+    projects: 0,
+    existingprojects: 0,
+    timeentries: 0,
+    payentries: 0,
+    allocations: 0,
+    rates: 0,
+    invoices: 0,
+    deleted: 0,
+    skipped: 0,
+    conflicts: Vec::new(),
+    invalid: Vec::new(),
+  };
+
+  for pe in export.projects.iter() {
+    let pt = &pe.time;
+    let p = &pt.project;
+    let conflict = |table: &str, user: Option<i64>, date: i64| ImportConflict {
+      project: p.name.clone(),
+      table: table.to_string(),
+      user: user,
+      date: date,
+    };
+    let pid = match imported_project(&tx, p.name.as_str(), p.createdate)? {
+      Some(pid) => {
+        report.existingprojects += 1;
+        pid
+      }
+      None => {
+        tx.execute(
+          "insert into project (name, description, due_days, extra_fields, invoice_id_template, invoice_seq, payer, payee, generic_task, public, rate, currency, distribution, lockdate, alert_threshold, reject_overlaps, createdate, changeddate)
+             values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+          params![
+            p.name,
+            p.description,
+            p.due_days,
+            serde_json::to_value(&p.extra_fields)?.to_string(),
+            p.invoice_id_template,
+            p.invoice_seq,
+            p.payer,
+            p.payee,
+            p.generic_task,
+            p.public,
+            p.rate,
+            p.currency,
+            serde_json::to_value(&p.distribution)?.to_string(),
+            p.lockdate,
+            p.alert_threshold,
+            p.reject_overlaps,
+            p.createdate,
+            p.changeddate
+          ],
+        )?;
+        report.projects += 1;
+        tx.last_insert_rowid()
+      }
+    };
+
+    // members already in the project keep their role and rate.
+    for m in pt.members.iter() {
+      let uid = import_uid(&tx, &export, &mut uidmap, m.id)?;
+      tx.execute(
+        "insert into projectmember (project, user, role, rate)
+         values (?1, ?2, ?3, ?4)
+         on conflict (project, user) do nothing",
+        params![pid, uid, m.role.to_string().as_str(), m.rate],
+      )?;
+    }
+
+    for r in pe.rates.iter() {
+      let user = match r.user {
+        Some(u) => Some(import_uid(&tx, &export, &mut uidmap, u)?),
+        None => None,
+      };
+      let creator = import_uid(&tx, &export, &mut uidmap, r.creator)?;
+      let duplicate: bool = tx.query_row(
+        "select exists (select * from projectrate where
+           project = ?1 and user is ?2 and rate is ?3 and effectivedate = ?4)",
+        params![pid, user, r.rate, r.effectivedate],
+        |row| row.get(0),
+      )?;
+      if duplicate {
+        report.skipped += 1;
+        continue;
+      }
+      tx.execute(
+        "insert into projectrate (project, user, rate, effectivedate, createdate, creator)
+         values (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![pid, user, r.rate, r.effectivedate, r.createdate, creator],
+      )?;
+      report.rates += 1;
+    }
+
+    // task ids in the export -> task ids in this db.
+    let mut taskmap = HashMap::new();
+    for t in pt.tasks.iter() {
+      let assignee = match t.assignee {
+        Some(a) => Some(import_uid(&tx, &export, &mut uidmap, a)?),
+        None => None,
+      };
+      let creator = import_uid(&tx, &export, &mut uidmap, t.creator)?;
+      let existing = match tx.query_row(
+        "select id from task where project = ?1 and name = ?2 and createdate = ?3",
+        params![pid, t.name, t.createdate],
+        |row| row.get::<usize, i64>(0),
+      ) {
+        Ok(id) => Some(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(e.into()),
+      };
+      let id = match existing {
+        Some(id) => id,
+        None => {
+          tx.execute(
+            "insert into task (project, name, estimate, status, assignee, createdate, changeddate, creator)
+             values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![pid, t.name, t.estimate, t.status.to_string(), assignee, t.createdate, t.changeddate, creator],
+          )?;
+          tx.last_insert_rowid()
+        }
+      };
+      taskmap.insert(t.id, id);
     }
-    for id in spt.deletetimeentries {
-      delete_time_entry(conn, uid, id)?;
+
+    // time entry ids in the export -> time entry ids in this db, for invoice
+    // links.  deleted entries come in deleted as of their changeddate.
+    let mut temap = HashMap::new();
+    let live = pt.timeentries.iter().map(|te| (te, None));
+    let deleted = pe
+      .deletedtimeentries
+      .iter()
+      .map(|te| (te, Some(te.changeddate)));
+    for (te, deleteddate) in live.chain(deleted) {
+      let uid = import_uid(&tx, &export, &mut uidmap, te.user)?;
+      let creator = import_uid(&tx, &export, &mut uidmap, te.creator)?;
+      let task = te.task.and_then(|t| taskmap.get(&t).copied());
+      match tx.query_row(
+        "select id from timeentry where
+           project = ?1 and user = ?2 and startdate = ?3 and enddate = ?4 and
+           (deleted is null) = (?5 is null)",
+        params![pid, uid, te.startdate, te.enddate, deleteddate],
+        |row| row.get::<usize, i64>(0),
+      ) {
+        Ok(id) => {
+          temap.insert(te.id, id);
+          report.skipped += 1;
+          continue;
+        }
+        Err(rusqlite::Error::QueryReturnedNoRows) => (),
+        Err(e) => return Err(e.into()),
+      };
+      // live entries get the same checks as saved ones.
+      if deleteddate.is_none() {
+        let ste = SaveTimeEntry {
+          id: None,
+          project: pid,
+          user: uid,
+          ..resave(te)
+        };
+        if check_time_entry(&tx, &ste)?.iter().any(|i| i.error) {
+          report
+            .invalid
+            .push(conflict("timeentry", Some(uid), te.startdate));
+          continue;
+        }
+      }
+      match tx.execute(
+        "insert or ignore into timeentry (project, user, description, startdate, enddate, ignore, task, createdate, changeddate, creator, deleted)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![pid, uid, te.description, te.startdate, te.enddate, te.ignore, task, te.createdate, te.changeddate, creator, deleteddate],
+      )? {
+        0 => report.conflicts.push(conflict("timeentry", Some(uid), te.startdate)),
+        _ => {
+          let id = tx.last_insert_rowid();
+          set_time_entry_tags(&tx, id, pid, &te.tags)?;
+          let after = read_time_entry(&tx, id)?;
+          audit_import(
+            &tx,
+            creator,
+            pid,
+            "timeentry",
+            id,
+            &after,
+            deleteddate.is_some(),
+          )?;
+          temap.insert(te.id, id);
+          match deleteddate {
+            Some(_) => report.deleted += 1,
+            None => report.timeentries += 1,
+          }
+        }
+      }
     }
-    for te in spt.savepayentries {
-      save_pay_entry(conn, uid, te)?;
+
+    for pe in pt.payentries.iter() {
+      let uid = import_uid(&tx, &export, &mut uidmap, pe.user)?;
+      let creator = import_uid(&tx, &export, &mut uidmap, pe.creator)?;
+      let paytype = match pe.paytype {
+        PayType::Invoiced => 0,
+        PayType::Paid => 1,
+      };
+      let duplicate: bool = tx.query_row(
+        "select exists (select * from payentry where
+           project = ?1 and user = ?2 and paymentdate = ?3 and duration = ?4 and type = ?5)",
+        params![pid, uid, pe.paymentdate, pe.duration, paytype],
+        |row| row.get(0),
+      )?;
+      if duplicate {
+        report.skipped += 1;
+        continue;
+      }
+      match tx.execute(
+        "insert or ignore into payentry (project, user, description, duration, type, paymentdate, createdate, changeddate, creator)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![pid, uid, pe.description, pe.duration, paytype, pe.paymentdate, pe.createdate, pe.changeddate, creator],
+      )? {
+        0 => report.conflicts.push(conflict("payentry", Some(uid), pe.paymentdate)),
+        _ => {
+          let id = tx.last_insert_rowid();
+          audit_import(&tx, creator, pid, "payentry", id, &read_pay_entry(&tx, id)?, false)?;
+          report.payentries += 1;
+        }
+      }
     }
-    for id in spt.deletepayentries {
-      delete_pay_entry(conn, uid, id)?;
+
+    let live = pt.allocations.iter().map(|a| (a, None));
+    let deleted = pe
+      .deletedallocations
+      .iter()
+      .map(|a| (a, Some(a.changeddate)));
+    for (a, deleteddate) in live.chain(deleted) {
+      let creator = import_uid(&tx, &export, &mut uidmap, a.creator)?;
+      let duplicate: bool = tx.query_row(
+        "select exists (select * from allocation where
+           project = ?1 and allocationdate = ?2 and duration = ?3 and
+           (deleted is null) = (?4 is null))",
+        params![pid, a.allocationdate, a.duration, deleteddate],
+        |row| row.get(0),
+      )?;
+      if duplicate {
+        report.skipped += 1;
+        continue;
+      }
+      match tx.execute(
+        "insert or ignore into allocation (project, description, duration, allocationdate, createdate, changeddate, creator, deleted)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![pid, a.description, a.duration, a.allocationdate, a.createdate, a.changeddate, creator, deleteddate],
+      )? {
+        0 => report.conflicts.push(conflict("allocation", None, a.allocationdate)),
+        _ => {
+          let id = tx.last_insert_rowid();
+          let after = read_allocation(&tx, id)?;
+          audit_import(
+            &tx,
+            creator,
+            pid,
+            "allocation",
+            id,
+            &after,
+            deleteddate.is_some(),
+          )?;
+          match deleteddate {
+            Some(_) => report.deleted += 1,
+            None => report.allocations += 1,
+          }
+        }
+      }
     }
-    for te in spt.saveallocations {
-      save_allocation(conn, uid, te)?;
+
+    // invoice numbers are unique in a project; an invoice already there is
+    // kept as is.
+    let mut invmap = HashMap::new();
+    for i in pe.invoices.iter() {
+      let creator = import_uid(&tx, &export, &mut uidmap, i.creator)?;
+      match tx.query_row(
+        "select id from invoice where project = ?1 and number = ?2",
+        params![pid, i.number],
+        |row| row.get::<usize, i64>(0),
+      ) {
+        Ok(id) => {
+          invmap.insert(i.id, id);
+          report.skipped += 1;
+          continue;
+        }
+        Err(rusqlite::Error::QueryReturnedNoRows) => (),
+        Err(e) => return Err(e.into()),
+      };
+      tx.execute(
+        "insert into invoice (project, number, date, due_date, payer, payee, items, extra_fields, total, currency, status, createdate, changeddate, creator)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+          pid,
+          i.number,
+          i.date,
+          i.due_date,
+          i.payer,
+          i.payee,
+          serde_json::to_value(&i.items)?.to_string(),
+          serde_json::to_value(&i.extra_fields)?.to_string(),
+          i.total,
+          i.currency,
+          i.status.to_string(),
+          i.createdate,
+          i.changeddate,
+          creator
+        ],
+      )?;
+      invmap.insert(i.id, tx.last_insert_rowid());
+      report.invoices += 1;
     }
-    for id in spt.deleteallocations {
-      delete_allocation(conn, uid, id)?;
+
+    for l in pe.invoicetimeentries.iter() {
+      if let (Some(invoice), Some(timeentry)) = (invmap.get(&l.invoice), temap.get(&l.timeentry)) {
+        tx.execute(
+          "insert or ignore into invoicetimeentry (invoice, timeentry) values (?1, ?2)",
+          params![invoice, timeentry],
+        )?;
+      }
     }
   }
 
-  read_project_time(conn, spt.project)
+  tx.commit()?;
+
+  Ok(report)
+}
+
+// the entry's id and any warnings, or the errors that kept it from being
+// saved.  locked entries, billed or before the project lock date, are only
+// saved with 'unlock'.
+pub fn save_time_entry(
+  conn: &Connection,
+  uid: i64,
+  spt: SaveTimeEntry,
+  unlock: bool,
+) -> Result<Result<(i64, Vec<TimeEntryIssue>), Vec<TimeEntryIssue>>, orgauth::error::Error> {
+  let now = now()?;
+  if let Some(task) = spt.task {
+    if read_task(conn, task)?.project != spt.project {
+      return Err(format!("task {} is not in project {}", task, spt.project).into());
+    }
+  }
+  let before = match spt.id {
+    Some(id) => Some(read_time_entry(conn, id)?),
+    None => None,
+  };
+  // moving an entry to another project takes a member or admin role in both.
+  if let Some(b) = before.as_ref() {
+    if b.project != spt.project
+      && !(time_editor(conn, uid, b.project)? && time_editor(conn, uid, spt.project)?)
+    {
+      return Err(format!("time entry {} is not in project {}", b.id, spt.project).into());
+    }
+  }
+  if !unlock {
+    // the entry as it is now, and as it would be.
+    let lock = match before.as_ref() {
+      Some(b) => time_entry_lock(conn, b.startdate, b.project, b.billed)?,
+      None => None,
+    };
+    let lock = match lock {
+      Some(reason) => Some(reason),
+      None => time_entry_lock(conn, spt.startdate, spt.project, false)?,
+    };
+    if let Some(reason) = lock {
+      return Err(format!("time entry is locked: {}", reason).into());
+    }
+  }
+  let issues = check_time_entry(conn, &spt)?;
+  if issues.iter().any(|i| i.error) {
+    return Ok(Err(issues));
+  }
+  let id = match spt.id {
+    Some(id) => {
+      conn.execute(
+        "update timeentry set
+            project =?1,
+            user = ?2,
+            description = ?3,
+            startdate = ?4,
+            enddate = ?5,
+            ignore = ?6,
+            task = case when ?10 or (?7 is null and project != ?1) then null else coalesce(?7, task) end,
+            changeddate = ?8
+          where id = ?9",
+        params![
+          spt.project,
+          spt.user,
+          spt.description,
+          spt.startdate,
+          spt.enddate,
+          spt.ignore,
+          spt.task,
+          now,
+          id,
+          spt.clear_task
+        ],
+      )?;
+      id
+    }
+    None => {
+      conn.execute(
+        "insert into timeentry (project, user, description, startdate, enddate, ignore, task, createdate, changeddate, creator)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![spt.project, spt.user, spt.description, spt.startdate, spt.enddate, spt.ignore, spt.task, now, now, uid],
+      )?;
+      conn.last_insert_rowid()
+    }
+  };
+  // tags belong to a project; an entry moved without new tags takes its
+  // tags to the new project.
+  match (spt.tags, before.as_ref()) {
+    (Some(tags), _) => set_time_entry_tags(conn, id, spt.project, &tags)?,
+    (None, Some(b)) if b.project != spt.project => {
+      set_time_entry_tags(conn, id, spt.project, &b.tags)?
+    }
+    _ => (),
+  }
+  let after = read_time_entry(conn, id)?;
+  record_audit(
+    conn,
+    uid,
+    after.project,
+    "timeentry",
+    id,
+    before.as_ref(),
+    Some(&after),
+  )?;
+  Ok(Ok((
+    id,
+    issues
+      .into_iter()
+      .map(|i| TimeEntryIssue {
+        entry: Some(id),
+        ..i
+      })
+      .collect(),
+  )))
+}
+
+// time entries ending more than this far in the future get a warning.
+const MAX_FUTURE: i64 = 365 * MILLIS_PER_DAY;
+
+// the save that would write 'te' as it is, for checking it.
+fn resave(te: &TimeEntry) -> SaveTimeEntry {
+  SaveTimeEntry {
+    id: Some(te.id),
+    project: te.project,
+    user: te.user,
+    description: te.description.clone(),
+    startdate: te.startdate,
+    enddate: te.enddate,
+    ignore: te.ignore,
+    tags: None,
+    task: None,
+    clear_task: false,
+    changeddate: None,
+  }
+}
+
+pub fn check_time_entry(
+  conn: &Connection,
+  ste: &SaveTimeEntry,
+) -> Result<Vec<TimeEntryIssue>, orgauth::error::Error> {
+  let now = now()?;
+  // (check, error)
+  let mut checks = Vec::new();
+  if ste.enddate < ste.startdate {
+    checks.push((TimeEntryCheck::EndBeforeStart, true));
+  } else if ste.enddate == ste.startdate {
+    checks.push((TimeEntryCheck::ZeroLength, false));
+  }
+  if ste.enddate > now + MAX_FUTURE {
+    checks.push((TimeEntryCheck::FarFuture, false));
+  }
+  let mut issues: Vec<TimeEntryIssue> = checks
+    .into_iter()
+    .map(|(c, e)| TimeEntryIssue {
+      entry: ste.id,
+      check: c,
+      error: e,
+      other: None,
+    })
+    .collect();
+
+  // the user's other entries, in any project.  ignored entries don't count.
+  if !ste.ignore && ste.enddate > ste.startdate {
+    let reject = read_project(conn, ste.project)?.reject_overlaps;
+    let mut pstmt = conn.prepare(
+      "select id from timeentry where
+        user = ?1 and
+        (?2 is null or id != ?2) and
+        deleted is null and
+        ignore = 0 and
+        startdate < ?4 and
+        enddate > ?3
+        order by startdate",
+    )?;
+    let others = pstmt
+      .query_map(
+        params![ste.user, ste.id, ste.startdate, ste.enddate],
+        |row| row.get(0),
+      )?
+      .collect::<Result<Vec<i64>, rusqlite::Error>>()?;
+    for other in others {
+      issues.push(TimeEntryIssue {
+        entry: ste.id,
+        check: TimeEntryCheck::Overlap,
+        error: reject,
+        other: Some(other),
+      });
+    }
+  }
+  Ok(issues)
+}
+
+// replace an entry's tags, adding any new ones to the project.
+pub fn set_time_entry_tags(
+  conn: &Connection,
+  teid: i64,
+  project: i64,
+  tags: &[String],
+) -> Result<(), orgauth::error::Error> {
+  conn.execute(
+    "delete from timeentrytag where timeentry = ?1",
+    params![teid],
+  )?;
+  for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+    conn.execute(
+      "insert or ignore into projecttag (project, name) values (?1, ?2)",
+      params![project, tag],
+    )?;
+    conn.execute(
+      "insert or ignore into timeentrytag (timeentry, tag)
+         select ?1, id from projecttag where project = ?2 and name = ?3",
+      params![teid, project, tag],
+    )?;
+  }
+  Ok(())
+}
+
+pub fn project_tags(
+  conn: &Connection,
+  projectid: i64,
+) -> Result<Vec<String>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare("select name from projecttag where project = ?1 order by name")?;
+  let r = pstmt
+    .query_map(params![projectid], |row| row.get(0))?
+    .collect::<Result<Vec<String>, rusqlite::Error>>()?;
+  Ok(r)
+}
+
+// hours per tag and user.  ignored entries are left out.
+pub fn tag_report(conn: &Connection, projectid: i64) -> Result<TagReport, orgauth::error::Error> {
+  let mut totals: BTreeMap<(Option<String>, i64), i64> = BTreeMap::new();
+  for te in time_entries(conn, projectid)?
+    .iter()
+    .filter(|te| !te.ignore)
+  {
+    let duration = te.enddate - te.startdate;
+    if te.tags.is_empty() {
+      *totals.entry((None, te.user)).or_insert(0) += duration;
+    }
+    for tag in te.tags.iter() {
+      *totals.entry((Some(tag.clone()), te.user)).or_insert(0) += duration;
+    }
+  }
+  Ok(TagReport {
+    project: projectid,
+    tags: totals
+      .into_iter()
+      .map(|((tag, user), duration)| TagHours {
+        tag: tag,
+        user: user,
+        duration: duration,
+      })
+      .collect(),
+  })
+}
+
+pub fn read_time_entry(conn: &Connection, id: i64) -> Result<TimeEntry, orgauth::error::Error> {
+  let te = conn.query_row(
+    "select te.id, te.project, te.user, te.description, te.startdate, te.enddate, te.ignore, te.createdate, te.changeddate, te.creator,
+            exists (select * from invoicetimeentry ite, invoice i where
+              ite.timeentry = te.id and ite.invoice = i.id and i.status != 'Void'),
+            te.task
+          from timeentry te where
+    te.id = ?1",
+    params![id],
+    |row| time_entry_from_row(row),
+  )?;
+  Ok(add_tags(conn, vec![te], "te.id = ?1", id)?.remove(0))
+}
+
+// check for user membership before calling!
+// locked entries are only deleted with 'unlock'.
+pub fn delete_time_entry(
+  conn: &Connection,
+  uid: i64,
+  teid: i64,
+  unlock: bool,
+) -> Result<(), orgauth::error::Error> {
+  let now = now()?;
+  let before = read_time_entry(conn, teid)?;
+  if !unlock {
+    if let Some(reason) = time_entry_lock(conn, before.startdate, before.project, before.billed)? {
+      return Err(format!("time entry {} is locked: {}", teid, reason).into());
+    }
+  }
+  conn.execute(
+    "update timeentry set deleted = ?1, changeddate = ?1 where id = ?2",
+    params![now, teid],
+  )?;
+  record_audit::<TimeEntry>(
+    conn,
+    uid,
+    before.project,
+    "timeentry",
+    teid,
+    Some(&before),
+    None,
+  )?;
+  Ok(())
+}
+
+// check for admin role before calling!
+// a new threshold gets a fresh alert.
+pub fn set_project_alert(
+  conn: &Connection,
+  project: i64,
+  threshold: Option<i64>,
+) -> Result<Project, orgauth::error::Error> {
+  let now = now()?;
+  conn.execute(
+    "update project set alert_threshold = ?1, changeddate = ?2 where id = ?3",
+    params![threshold, now, project],
+  )?;
+  clear_alerts_sent(conn, project)?;
+  read_project(conn, project)
+}
+
+// projects with an alert threshold.
+pub fn alert_projects(conn: &Connection) -> Result<Vec<Project>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare("select id from project where alert_threshold is not null")?;
+  let ids = pstmt
+    .query_map(params![], |row| row.get(0))?
+    .collect::<Result<Vec<i64>, rusqlite::Error>>()?;
+  ids.iter().map(|id| read_project(conn, *id)).collect()
+}
+
+pub fn set_alert_sent(
+  conn: &Connection,
+  project: i64,
+  user: i64,
+) -> Result<(), orgauth::error::Error> {
+  let now = now()?;
+  conn.execute(
+    "insert or ignore into alertsent (project, user, sentdate) values (?1, ?2, ?3)",
+    params![project, user, now],
+  )?;
+  Ok(())
+}
+
+pub fn clear_alerts_sent(conn: &Connection, project: i64) -> Result<(), orgauth::error::Error> {
+  conn.execute("delete from alertsent where project = ?1", params![project])?;
+  Ok(())
+}
+
+// (user, email, alert sent) for the project's admins with an email.
+pub fn alert_recipients(
+  conn: &Connection,
+  project: i64,
+) -> Result<Vec<(i64, String, bool)>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select orgauth_user.id, orgauth_user.email,
+          exists (select * from alertsent where
+            alertsent.project = projectmember.project and alertsent.user = orgauth_user.id)
+          from orgauth_user, projectmember where
+          orgauth_user.id = projectmember.user and
+          projectmember.project = ?1 and
+          projectmember.role = 'Admin' and
+          orgauth_user.email != ''",
+  )?;
+  let r = pstmt
+    .query_map(params![project], |row| {
+      Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?
+    .collect::<Result<Vec<(i64, String, bool)>, rusqlite::Error>>()?;
+  Ok(r)
+}
+
+// check for admin role before calling!
+pub fn set_project_lock(
+  conn: &Connection,
+  project: i64,
+  lockdate: Option<i64>,
+) -> Result<Project, orgauth::error::Error> {
+  let now = now()?;
+  conn.execute(
+    "update project set lockdate = ?1, changeddate = ?2 where id = ?3",
+    params![lockdate, now, project],
+  )?;
+  read_project(conn, project)
+}
+
+// why a time entry can't be changed, if it can't.
+fn time_entry_lock(
+  conn: &Connection,
+  startdate: i64,
+  project: i64,
+  billed: bool,
+) -> Result<Option<String>, orgauth::error::Error> {
+  if billed {
+    return Ok(Some("billed on an invoice".to_string()));
+  }
+  match read_project(conn, project)?.lockdate {
+    Some(ld) if startdate < ld => Ok(Some(format!("before the project lock date {}", ld))),
+    _ => Ok(None),
+  }
+}
+
+// time entry saves and deletes in 'spt' that touch locked entries, either
+// billed or before the project lock date.
+pub fn locked_time_entries(
+  conn: &Connection,
+  spt: &SaveProjectTime,
+) -> Result<Vec<LockedTimeEntry>, orgauth::error::Error> {
+  let mut locked = Vec::new();
+  for ste in spt.savetimeentries.iter() {
+    // the entry as it is now.  entries from other projects aren't reported;
+    // saving them fails.
+    if let Some(id) = ste.id {
+      let te = read_time_entry(conn, id)?;
+      if te.project != spt.project {
+        continue;
+      }
+      if let Some(reason) = time_entry_lock(conn, te.startdate, te.project, te.billed)? {
+        locked.push(LockedTimeEntry {
+          id: Some(id),
+          reason: reason,
+        });
+        continue;
+      }
+    }
+    // the entry as it would be.
+    if let Some(reason) = time_entry_lock(conn, ste.startdate, ste.project, false)? {
+      locked.push(LockedTimeEntry {
+        id: ste.id,
+        reason: reason,
+      });
+    }
+  }
+  for de in spt.deletetimeentries.iter() {
+    let te = read_time_entry(conn, de.id())?;
+    if te.project != spt.project {
+      continue;
+    }
+    if let Some(reason) = time_entry_lock(conn, te.startdate, te.project, te.billed)? {
+      locked.push(LockedTimeEntry {
+        id: Some(te.id),
+        reason: reason,
+      });
+    }
+  }
+  Ok(locked)
+}
+
+// 'unlock' lets locked time entries change; see save_time_entry.
+pub fn save_project_time(
+  conn: &Connection,
+  uid: i64,
+  spt: SaveProjectTime,
+  unlock: bool,
+) -> Result<Result<ProjectTime, ProjectTimeError>, orgauth::error::Error> {
+  let project = spt.project;
+  let mut warnings = Vec::new();
+  if let Some(e) = other_project_item(conn, &spt)? {
+    return Ok(Err(e));
+  }
+  // is user a member of this project?
+  if is_project_member(conn, uid, project)? {
+    match all_or_nothing(conn, || save_project_time_items(conn, uid, spt, unlock))? {
+      Ok(w) => warnings = w,
+      Err(e) => return Ok(Err(e)),
+    }
+  }
+
+  let mut pt = read_project_time(conn, project)?;
+  pt.warnings = warnings;
+  Ok(Ok(pt))
+}
+
+// the first item in 'spt' that isn't in its project.  time entries in the
+// project can be moved out of it; save_time_entry checks the new project.
+fn other_project_item(
+  conn: &Connection,
+  spt: &SaveProjectTime,
+) -> Result<Option<ProjectTimeError>, orgauth::error::Error> {
+  let project = spt.project;
+  let item_error = |list: &str, index: usize, id: Option<i64>| {
+    Some(ProjectTimeError {
+      list: list.to_string(),
+      index: index,
+      id: id,
+      error: format!("not in project {}", project),
+      issues: Vec::new(),
+    })
+  };
+  for (i, de) in spt.deletetimeentries.iter().enumerate() {
+    if read_time_entry(conn, de.id())?.project != project {
+      return Ok(item_error("deletetimeentries", i, Some(de.id())));
+    }
+  }
+  for (i, ste) in spt.savetimeentries.iter().enumerate() {
+    let current = match ste.id {
+      Some(id) => read_time_entry(conn, id)?.project,
+      None => ste.project,
+    };
+    if current != project {
+      return Ok(item_error("savetimeentries", i, ste.id));
+    }
+  }
+  for (i, spe) in spt.savepayentries.iter().enumerate() {
+    let current = match spe.id {
+      Some(id) => read_pay_entry(conn, id)?.project,
+      None => spe.project,
+    };
+    if current != project || spe.project != project {
+      return Ok(item_error("savepayentries", i, spe.id));
+    }
+  }
+  for (i, de) in spt.deletepayentries.iter().enumerate() {
+    if read_pay_entry(conn, de.id())?.project != project {
+      return Ok(item_error("deletepayentries", i, Some(de.id())));
+    }
+  }
+  for (i, sa) in spt.saveallocations.iter().enumerate() {
+    let current = match sa.id {
+      Some(id) => read_allocation(conn, id)?.project,
+      None => sa.project,
+    };
+    if current != project || sa.project != project {
+      return Ok(item_error("saveallocations", i, sa.id));
+    }
+  }
+  for (i, de) in spt.deleteallocations.iter().enumerate() {
+    if read_allocation(conn, de.id())?.project != project {
+      return Ok(item_error("deleteallocations", i, Some(de.id())));
+    }
+  }
+  Ok(None)
+}
+
+// stops at the first item that fails.  returns time entry warnings.
+fn save_project_time_items(
+  conn: &Connection,
+  uid: i64,
+  spt: SaveProjectTime,
+  unlock: bool,
+) -> Result<Vec<TimeEntryIssue>, ProjectTimeError> {
+  let item_error =
+    |list: &str, index: usize, id: Option<i64>, e: orgauth::error::Error| ProjectTimeError {
+      list: list.to_string(),
+      index: index,
+      id: id,
+      error: e.to_string(),
+      issues: Vec::new(),
+    };
+  let mut warnings = Vec::new();
+  // deletes first, so they don't overlap with the saves.
+  for (i, de) in spt.deletetimeentries.into_iter().enumerate() {
+    delete_time_entry(conn, uid, de.id(), unlock)
+      .map_err(|e| item_error("deletetimeentries", i, Some(de.id()), e))?;
+  }
+  for (i, te) in spt.savetimeentries.into_iter().enumerate() {
+    let id = te.id;
+    match save_time_entry(conn, uid, te, unlock)
+      .map_err(|e| item_error("savetimeentries", i, id, e))?
+    {
+      Ok((_, w)) => warnings.extend(w),
+      Err(issues) => {
+        return Err(ProjectTimeError {
+          list: "savetimeentries".to_string(),
+          index: i,
+          id: id,
+          error: "time entry failed checks".to_string(),
+          issues: issues,
+        })
+      }
+    }
+  }
+  for (i, pe) in spt.savepayentries.into_iter().enumerate() {
+    let id = pe.id;
+    save_pay_entry(conn, uid, pe).map_err(|e| item_error("savepayentries", i, id, e))?;
+  }
+  for (i, de) in spt.deletepayentries.into_iter().enumerate() {
+    delete_pay_entry(conn, uid, de.id())
+      .map_err(|e| item_error("deletepayentries", i, Some(de.id()), e))?;
+  }
+  for (i, a) in spt.saveallocations.into_iter().enumerate() {
+    let id = a.id;
+    save_allocation(conn, uid, a).map_err(|e| item_error("saveallocations", i, id, e))?;
+  }
+  for (i, de) in spt.deleteallocations.into_iter().enumerate() {
+    delete_allocation(conn, uid, de.id())
+      .map_err(|e| item_error("deleteallocations", i, Some(de.id()), e))?;
+  }
+  Ok(warnings)
+}
+
+pub fn active_timer(
+  conn: &Connection,
+  uid: i64,
+) -> Result<Option<ActiveTimer>, orgauth::error::Error> {
+  match conn.query_row(
+    "select user, project, description, startdate from activetimer where user = ?1",
+    params![uid],
+    |row| {
+      Ok(ActiveTimer {
+        user: row.get(0)?,
+        project: row.get(1)?,
+        description: row.get(2)?,
+        startdate: row.get(3)?,
+      })
+    },
+  ) {
+    Ok(v) => Ok(Some(v)),
+    Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+    Err(x) => Err(x.into()),
+  }
+}
+
+// running timers in all the projects this user is a member of.
+pub fn active_timers(
+  conn: &Connection,
+  uid: i64,
+) -> Result<Vec<ActiveTimer>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select tmr.user, tmr.project, tmr.description, tmr.startdate
+          from activetimer tmr, projectmember pm where
+          tmr.project = pm.project and
+          pm.user = ?1",
+  )?;
+  let r = Ok(
+    pstmt
+      .query_map(params![uid], |row| {
+        Ok(ActiveTimer {
+          user: row.get(0)?,
+          project: row.get(1)?,
+          description: row.get(2)?,
+          startdate: row.get(3)?,
+        })
+      })?
+      .filter_map(|x| x.ok())
+      .collect(),
+  );
+  r
+}
+
+// check for user membership before calling!
+// if a timer is already running, it is clocked out first.
+pub fn clock_in(
+  conn: &Connection,
+  uid: i64,
+  ci: ClockIn,
+) -> Result<ActiveTimer, orgauth::error::Error> {
+  // if the new timer can't start, the old one keeps running.
+  all_or_nothing(conn, || -> Result<ActiveTimer, orgauth::error::Error> {
+    clock_out(conn, uid, ClockOut { description: None })?;
+
+    let now = now()?;
+    conn.execute(
+      "insert into activetimer (user, project, description, startdate)
+       values (?1, ?2, ?3, ?4)",
+      params![uid, ci.project, ci.description, now],
+    )?;
+
+    Ok(ActiveTimer {
+      user: uid,
+      project: ci.project,
+      description: ci.description,
+      startdate: now,
+    })
+  })?
+}
+
+// stop the user's running timer, saving it as a timeentry.
+pub fn clock_out(
+  conn: &Connection,
+  uid: i64,
+  co: ClockOut,
+) -> Result<Option<TimeEntry>, orgauth::error::Error> {
+  // the timer only stops if its time entry is saved.
+  all_or_nothing(
+    conn,
+    || -> Result<Option<TimeEntry>, orgauth::error::Error> {
+      match active_timer(conn, uid)? {
+        None => Ok(None),
+        Some(at) => {
+          let now = now()?;
+          let id = match save_time_entry(
+            conn,
+            uid,
+            SaveTimeEntry {
+              id: None,
+              project: at.project,
+              user: uid,
+              description: co.description.unwrap_or(at.description),
+              startdate: at.startdate,
+              enddate: now,
+              ignore: false,
+              tags: None,
+              task: None,
+              clear_task: false,
+              changeddate: None,
+            },
+            false,
+          )? {
+            Ok((id, _)) => id,
+            Err(issues) => {
+              let checks: Vec<String> = issues.iter().map(|i| i.check.to_string()).collect();
+              return Err(format!("time entry not saved: {}", checks.join(", ")).into());
+            }
+          };
+          conn.execute("delete from activetimer where user = ?1", params![uid])?;
+          Ok(Some(read_time_entry(conn, id)?))
+        }
+      }
+    },
+  )?
+}
+
+// record a printed invoice.  reprinting a draft updates it; invoices that
+// have been sent, paid or voided are left as they are.
+// entries already billed by another invoice aren't linked again.
+pub fn save_invoice(
+  conn: &Connection,
+  uid: i64,
+  project: i64,
+  pi: &PrintInvoice,
+) -> Result<i64, orgauth::error::Error> {
+  let now = now()?;
+  let total: f64 = pi.items.iter().map(|i| i.duration * i.rate).sum();
+  conn.execute(
+    "insert into invoice (project, number, date, due_date, payer, payee, items, extra_fields, total, currency, status, createdate, changeddate, creator)
+     values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+     on conflict (project, number) do update set
+       date = ?3,
+       due_date = ?4,
+       payer = ?5,
+       payee = ?6,
+       items = ?7,
+       extra_fields = ?8,
+       total = ?9,
+       currency = ?10,
+       changeddate = ?13
+     where invoice.status = 'Draft'",
+    params![
+      project,
+      pi.id,
+      pi.date,
+      pi.due_date,
+      pi.payer,
+      pi.payee,
+      serde_json::to_value(&pi.items)?.to_string(),
+      serde_json::to_value(&pi.extra_fields)?.to_string(),
+      total,
+      pi.currency,
+      InvoiceStatus::Draft.to_string(),
+      now,
+      now,
+      uid
+    ],
+  )?;
+
+  let (id, status) = conn.query_row(
+    "select id, status from invoice where project = ?1 and number = ?2",
+    params![project, pi.id],
+    |row| Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?)),
+  )?;
+
+  match (&pi.timeentries, InvoiceStatus::from_str(status.as_str())) {
+    (Some(teids), Ok(InvoiceStatus::Draft)) => {
+      conn.execute(
+        "delete from invoicetimeentry where invoice = ?1",
+        params![id],
+      )?;
+      for teid in teids {
+        conn.execute(
+          "insert into invoicetimeentry (invoice, timeentry)
+           select ?1, te.id from timeentry te where
+             te.id = ?2 and
+             te.project = ?3 and
+             te.deleted is null and
+             not exists (select * from invoicetimeentry ite, invoice i where
+               ite.timeentry = te.id and ite.invoice = i.id and i.status != 'Void')",
+          params![id, teid, project],
+        )?;
+      }
+    }
+    _ => (),
+  }
+
+  Ok(id)
+}
+
+// project time entries for a new invoice.  unbilled and not ignored, unless
+// 'include_billed'.  suggested items total the entries for each member.
+pub fn invoice_time(
+  conn: &Connection,
+  projectid: i64,
+  include_billed: bool,
+) -> Result<InvoiceTime, orgauth::error::Error> {
+  let timeentries: Vec<TimeEntry> = time_entries(conn, projectid)?
+    .into_iter()
+    .filter(|te| !te.ignore && (include_billed || !te.billed))
+    .collect();
+
+  let rates = rates::project_rates(conn, projectid)?;
+  let mut items = Vec::new();
+  for m in member_list(conn, projectid)? {
+    // millis at each rate, in order of first use.  priced by day, as in
+    // distributions.
+    let mut byrate: Vec<(f64, i64)> = Vec::new();
+    for te in timeentries.iter().filter(|te| te.user == m.id) {
+      for (day, dm) in millis_per_day(te.startdate, te.enddate) {
+        let rate = rates.day_rate(m.id, day).unwrap_or(0.0);
+        match byrate.iter_mut().find(|(r, _)| *r == rate) {
+          Some((_, millis)) => *millis += dm,
+          None => byrate.push((rate, dm)),
+        }
+      }
+    }
+    for (rate, millis) in byrate {
+      if millis > 0 {
+        items.push(InvoiceItem {
+          description: m.name.clone(),
+          duration: millis as f64 / MILLIS_PER_HOUR as f64,
+          rate: rate,
+        });
+      }
+    }
+  }
+
+  Ok(InvoiceTime {
+    timeentries: timeentries,
+    items: items,
+  })
+}
+
+fn invoice_from_row(row: &rusqlite::Row) -> Result<Invoice, rusqlite::Error> {
+  Ok(Invoice {
+    id: row.get(0)?,
+    project: row.get(1)?,
+    number: row.get(2)?,
+    date: row.get(3)?,
+    due_date: row.get(4)?,
+    payer: row.get(5)?,
+    payee: row.get(6)?,
+    items: serde_json::from_str(row.get::<usize, String>(7)?.as_str()).map_err(|e| {
+      rusqlite::Error::FromSqlConversionFailure(7, rusqlite::types::Type::Text, Box::new(e))
+    })?,
+    extra_fields: serde_json::from_str(row.get::<usize, String>(8)?.as_str()).map_err(|e| {
+      rusqlite::Error::FromSqlConversionFailure(8, rusqlite::types::Type::Text, Box::new(e))
+    })?,
+    total: row.get(9)?,
+    currency: row.get(10)?,
+    status: match InvoiceStatus::from_str(row.get::<usize, String>(11)?.as_str()) {
+      Ok(s) => s,
+      Err(_) => {
+        return Err(rusqlite::Error::InvalidColumnType(
+          11,
+          "status".to_string(),
+          rusqlite::types::Type::Text,
+        ));
+      }
+    },
+    createdate: row.get(12)?,
+    changeddate: row.get(13)?,
+    creator: row.get(14)?,
+  })
+}
+
+pub fn invoices(conn: &Connection, projectid: i64) -> Result<Vec<Invoice>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select id, project, number, date, due_date, payer, payee, items, extra_fields, total, currency, status, createdate, changeddate, creator
+          from invoice where
+          project = ?1
+          order by date desc, id desc",
+  )?;
+  let r = pstmt
+    .query_map(params![projectid], |row| invoice_from_row(row))?
+    .collect::<Result<Vec<Invoice>, rusqlite::Error>>()?;
+  Ok(r)
+}
+
+pub fn read_invoice(conn: &Connection, id: i64) -> Result<Option<Invoice>, orgauth::error::Error> {
+  match conn.query_row(
+    "select id, project, number, date, due_date, payer, payee, items, extra_fields, total, currency, status, createdate, changeddate, creator
+          from invoice where
+          id = ?1",
+    params![id],
+    |row| invoice_from_row(row),
+  ) {
+    Ok(v) => Ok(Some(v)),
+    Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+    Err(x) => Err(x.into()),
+  }
+}
+
+// check for admin role before calling!
+pub fn set_invoice_status(
+  conn: &Connection,
+  id: i64,
+  status: InvoiceStatus,
+) -> Result<(), orgauth::error::Error> {
+  let now = now()?;
+  conn.execute(
+    "update invoice set status = ?1, changeddate = ?2 where id = ?3",
+    params![status.to_string(), now, id],
+  )?;
+  Ok(())
+}
+
+pub fn record_rate(
+  conn: &Connection,
+  uid: i64,
+  project: i64,
+  user: Option<i64>,
+  rate: Option<f64>,
+  effectivedate: i64,
+) -> Result<(), orgauth::error::Error> {
+  let now = now()?;
+  conn.execute(
+    "insert into projectrate (project, user, rate, effectivedate, createdate, creator)
+     values (?1, ?2, ?3, ?4, ?5, ?6)",
+    params![project, user, rate, effectivedate, now, uid],
+  )?;
+  Ok(())
+}
+
+// rate history, oldest first.
+pub fn project_rate_history(
+  conn: &Connection,
+  projectid: i64,
+) -> Result<Vec<ProjectRate>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select id, project, user, rate, effectivedate, createdate, creator
+          from projectrate where
+          project = ?1
+          order by effectivedate, id",
+  )?;
+  let r = pstmt
+    .query_map(params![projectid], |row| {
+      Ok(ProjectRate {
+        id: row.get(0)?,
+        project: row.get(1)?,
+        user: row.get(2)?,
+        rate: row.get(3)?,
+        effectivedate: row.get(4)?,
+        createdate: row.get(5)?,
+        creator: row.get(6)?,
+      })
+    })?
+    .collect::<Result<Vec<ProjectRate>, rusqlite::Error>>()?;
+  Ok(r)
+}
+
+// check for admin role before calling!
+// add a rate change, possibly backdated, and update the current rate to match.
+pub fn set_project_rate(
+  conn: &Connection,
+  uid: i64,
+  spr: SetProjectRate,
+) -> Result<Vec<ProjectRate>, orgauth::error::Error> {
+  let now = now()?;
+  record_rate(
+    conn,
+    uid,
+    spr.project,
+    spr.user,
+    spr.rate,
+    spr.effectivedate,
+  )?;
+
+  let rates = rates::project_rates(conn, spr.project)?;
+  match spr.user {
+    None => {
+      conn.execute(
+        "update project set rate = ?1, changeddate = ?2 where id = ?3",
+        params![rates.project_rate_at(now), now, spr.project],
+      )?;
+    }
+    Some(user) => {
+      conn.execute(
+        "update projectmember set rate = ?1 where project = ?2 and user = ?3",
+        params![rates.member_rate_at(user, now), spr.project, user],
+      )?;
+    }
+  }
+
+  project_rate_history(conn, spr.project)
+}
+
+fn api_token_hash(token: &str) -> String {
+  crypto_hash::hex_digest(crypto_hash::Algorithm::SHA256, token.as_bytes())
+}
+
+pub fn create_api_token(
+  conn: &Connection,
+  uid: i64,
+  nat: NewApiToken,
+) -> Result<CreatedApiToken, orgauth::error::Error> {
+  let now = now()?;
+  let token = Uuid::new_v4().to_string();
+  conn.execute(
+    "insert into apitoken (user, name, tokenhash, createdate)
+     values (?1, ?2, ?3, ?4)",
+    params![uid, nat.name, api_token_hash(token.as_str()), now],
+  )?;
+
+  Ok(CreatedApiToken {
+    apitoken: ApiToken {
+      id: conn.last_insert_rowid(),
+      name: nat.name,
+      createdate: now,
+      lastused: None,
+    },
+    token: token,
+  })
+}
+
+pub fn api_tokens(conn: &Connection, uid: i64) -> Result<Vec<ApiToken>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select id, name, createdate, lastused
+          from apitoken where
+          user = ?1
+          order by createdate",
+  )?;
+  let r = pstmt
+    .query_map(params![uid], |row| {
+      Ok(ApiToken {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        createdate: row.get(2)?,
+        lastused: row.get(3)?,
+      })
+    })?
+    .collect::<Result<Vec<ApiToken>, rusqlite::Error>>()?;
+  Ok(r)
+}
+
+pub fn revoke_api_token(
+  conn: &Connection,
+  uid: i64,
+  id: i64,
+) -> Result<Vec<ApiToken>, orgauth::error::Error> {
+  conn.execute(
+    "delete from apitoken where id = ?1 and user = ?2",
+    params![id, uid],
+  )?;
+  api_tokens(conn, uid)
+}
+
+// user id for a bearer token, if it's valid.
+pub fn api_token_user(
+  conn: &Connection,
+  token: &str,
+) -> Result<Option<i64>, orgauth::error::Error> {
+  let hash = api_token_hash(token);
+  match conn.query_row(
+    "select user from apitoken where tokenhash = ?1",
+    params![hash],
+    |row| Ok(row.get(0)?),
+  ) {
+    Ok(uid) => {
+      conn.execute(
+        "update apitoken set lastused = ?1 where tokenhash = ?2",
+        params![now()?, hash],
+      )?;
+      Ok(Some(uid))
+    }
+    Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+    Err(x) => Err(x.into()),
+  }
+}
+
+pub fn create_ics_feed(
+  conn: &Connection,
+  uid: i64,
+  mainsite: &str,
+  nif: NewIcsFeed,
+) -> Result<CreatedIcsFeed, orgauth::error::Error> {
+  let now = now()?;
+  let token = Uuid::new_v4().to_string();
+  conn.execute(
+    "insert into icsfeed (user, project, tokenhash, createdate)
+     values (?1, ?2, ?3, ?4)",
+    params![uid, nif.project, api_token_hash(token.as_str()), now],
+  )?;
+
+  Ok(CreatedIcsFeed {
+    icsfeed: IcsFeed {
+      id: conn.last_insert_rowid(),
+      project: nif.project,
+      createdate: now,
+    },
+    url: format!("{}/ics/{}.ics", mainsite, token),
+  })
+}
+
+pub fn ics_feeds(conn: &Connection, uid: i64) -> Result<Vec<IcsFeed>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select id, project, createdate
+          from icsfeed where
+          user = ?1
+          order by createdate",
+  )?;
+  let r = pstmt
+    .query_map(params![uid], |row| {
+      Ok(IcsFeed {
+        id: row.get(0)?,
+        project: row.get(1)?,
+        createdate: row.get(2)?,
+      })
+    })?
+    .collect::<Result<Vec<IcsFeed>, rusqlite::Error>>()?;
+  Ok(r)
+}
+
+pub fn revoke_ics_feed(
+  conn: &Connection,
+  uid: i64,
+  id: i64,
+) -> Result<Vec<IcsFeed>, orgauth::error::Error> {
+  conn.execute(
+    "delete from icsfeed where id = ?1 and user = ?2",
+    params![id, uid],
+  )?;
+  ics_feeds(conn, uid)
+}
+
+// (user, project) for a feed token, if it's valid.
+pub fn ics_feed_for_token(
+  conn: &Connection,
+  token: &str,
+) -> Result<Option<(i64, Option<i64>)>, orgauth::error::Error> {
+  match conn.query_row(
+    "select user, project from icsfeed where tokenhash = ?1",
+    params![api_token_hash(token)],
+    |row| Ok((row.get(0)?, row.get(1)?)),
+  ) {
+    Ok(f) => Ok(Some(f)),
+    Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+    Err(x) => Err(x.into()),
+  }
+}
+
+fn task_from_row(row: &rusqlite::Row) -> Result<Task, rusqlite::Error> {
+  Ok(Task {
+    id: row.get(0)?,
+    project: row.get(1)?,
+    name: row.get(2)?,
+    estimate: row.get(3)?,
+    status: TaskStatus::from_str(row.get::<usize, String>(4)?.as_str()).map_err(|_| {
+      rusqlite::Error::InvalidColumnType(4, "status".to_string(), rusqlite::types::Type::Text)
+    })?,
+    assignee: row.get(5)?,
+    createdate: row.get(6)?,
+    changeddate: row.get(7)?,
+    creator: row.get(8)?,
+  })
+}
+
+pub fn tasks(conn: &Connection, projectid: i64) -> Result<Vec<Task>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select id, project, name, estimate, status, assignee, createdate, changeddate, creator
+          from task where
+          project = ?1
+          order by id",
+  )?;
+  let r = pstmt
+    .query_map(params![projectid], |row| task_from_row(row))?
+    .collect::<Result<Vec<Task>, rusqlite::Error>>()?;
+  Ok(r)
+}
+
+pub fn read_task(conn: &Connection, id: i64) -> Result<Task, orgauth::error::Error> {
+  Ok(conn.query_row(
+    "select id, project, name, estimate, status, assignee, createdate, changeddate, creator
+          from task where
+          id = ?1",
+    params![id],
+    |row| task_from_row(row),
+  )?)
+}
+
+// check for member role before calling!
+pub fn save_task(conn: &Connection, uid: i64, st: SaveTask) -> Result<Task, orgauth::error::Error> {
+  let now = now()?;
+  if let Some(assignee) = st.assignee {
+    if !is_project_member(conn, assignee, st.project)? {
+      return Err(format!("user {} is not a project member", assignee).into());
+    }
+  }
+  let id = match st.id {
+    Some(id) => {
+      if read_task(conn, id)?.project != st.project {
+        return Err(format!("task {} is not in project {}", id, st.project).into());
+      }
+      conn.execute(
+        "update task set name = ?1, estimate = ?2, status = ?3, assignee = ?4, changeddate = ?5
+          where id = ?6",
+        params![
+          st.name,
+          st.estimate,
+          st.status.to_string(),
+          st.assignee,
+          now,
+          id
+        ],
+      )?;
+      id
+    }
+    None => {
+      conn.execute(
+        "insert into task (project, name, estimate, status, assignee, createdate, changeddate, creator)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![st.project, st.name, st.estimate, st.status.to_string(), st.assignee, now, now, uid],
+      )?;
+      conn.last_insert_rowid()
+    }
+  };
+  read_task(conn, id)
+}
+
+// check for admin role before calling!
+// time entries on the task are kept, without a task.  if any of them are
+// locked, nothing is deleted and they're returned.
+pub fn delete_task(
+  conn: &Connection,
+  uid: i64,
+  project: i64,
+  id: i64,
+) -> Result<Result<(), Vec<LockedTimeEntry>>, orgauth::error::Error> {
+  all_or_nothing(conn, || delete_task_inner(conn, uid, project, id))?
+}
+
+fn delete_task_inner(
+  conn: &Connection,
+  uid: i64,
+  project: i64,
+  id: i64,
+) -> Result<Result<(), Vec<LockedTimeEntry>>, orgauth::error::Error> {
+  let now = now()?;
+  let mut pstmt = conn.prepare("select id from timeentry where task = ?1 and project = ?2")?;
+  let teids = pstmt
+    .query_map(params![id, project], |row| row.get(0))?
+    .collect::<Result<Vec<i64>, rusqlite::Error>>()?;
+  let mut tes = Vec::new();
+  let mut locked = Vec::new();
+  for teid in teids {
+    let te = read_time_entry(conn, teid)?;
+    if let Some(reason) = time_entry_lock(conn, te.startdate, te.project, te.billed)? {
+      locked.push(LockedTimeEntry {
+        id: Some(teid),
+        reason: reason,
+      });
+    }
+    tes.push(te);
+  }
+  if !locked.is_empty() {
+    return Ok(Err(locked));
+  }
+
+  for before in tes {
+    conn.execute(
+      "update timeentry set task = null, changeddate = ?1 where id = ?2",
+      params![now, before.id],
+    )?;
+    let after = read_time_entry(conn, before.id)?;
+    record_audit(
+      conn,
+      uid,
+      project,
+      "timeentry",
+      before.id,
+      Some(&before),
+      Some(&after),
+    )?;
+  }
+  conn.execute(
+    "delete from task where id = ?1 and project = ?2",
+    params![id, project],
+  )?;
+  Ok(Ok(()))
+}
+
+pub fn task_report(conn: &Connection, projectid: i64) -> Result<TaskReport, orgauth::error::Error> {
+  let mut logged: HashMap<i64, i64> = HashMap::new();
+  let mut unassigned = 0;
+  for te in time_entries(conn, projectid)?
+    .iter()
+    .filter(|te| !te.ignore)
+  {
+    match te.task {
+      Some(t) => *logged.entry(t).or_insert(0) += te.enddate - te.startdate,
+      None => unassigned += te.enddate - te.startdate,
+    }
+  }
+  Ok(TaskReport {
+    project: projectid,
+    tasks: tasks(conn, projectid)?
+      .into_iter()
+      .map(|t| {
+        let l = logged.get(&t.id).copied().unwrap_or(0);
+        TaskHours {
+          remaining: t.estimate.map(|e| e - l),
+          logged: l,
+          task: t,
+        }
+      })
+      .collect(),
+    unassigned: unassigned,
+  })
+}
+
+// append a row to the audit table.  the action follows from which of before
+// and after are present.
+pub fn record_audit<T: serde::Serialize>(
+  conn: &Connection,
+  uid: i64,
+  project: i64,
+  tablename: &str,
+  recordid: i64,
+  before: Option<&T>,
+  after: Option<&T>,
+) -> Result<(), orgauth::error::Error> {
+  let action = match (before, after) {
+    (None, _) => AuditAction::Insert,
+    (Some(_), Some(_)) => AuditAction::Update,
+    (Some(_), None) => AuditAction::Delete,
+  };
+  insert_audit(
+    conn, uid, project, tablename, recordid, action, before, after,
+  )
+}
+
+fn insert_audit<T: serde::Serialize>(
+  conn: &Connection,
+  uid: i64,
+  project: i64,
+  tablename: &str,
+  recordid: i64,
+  action: AuditAction,
+  before: Option<&T>,
+  after: Option<&T>,
+) -> Result<(), orgauth::error::Error> {
+  let now = now()?;
+  let before = match before {
+    Some(b) => Some(serde_json::to_string(b)?),
+    None => None,
+  };
+  let after = match after {
+    Some(a) => Some(serde_json::to_string(a)?),
+    None => None,
+  };
+  conn.execute(
+    "insert into audit (project, user, tablename, recordid, action, before, after, changedate)
+     values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    params![
+      project,
+      uid,
+      tablename,
+      recordid,
+      action.to_string(),
+      before,
+      after,
+      now
+    ],
+  )?;
+  Ok(())
+}
+
+// most recent first.
+pub fn project_audit(
+  conn: &Connection,
+  projectid: i64,
+) -> Result<Vec<AuditEntry>, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select id, project, user, tablename, recordid, action, before, after, changedate
+          from audit where
+          project = ?1
+          order by id desc",
+  )?;
+  let rows = pstmt
+    .query_map(params![projectid], |row| {
+      Ok((
+        row.get::<usize, i64>(0)?,
+        row.get::<usize, i64>(1)?,
+        row.get::<usize, i64>(2)?,
+        row.get::<usize, String>(3)?,
+        row.get::<usize, i64>(4)?,
+        row.get::<usize, String>(5)?,
+        row.get::<usize, Option<String>>(6)?,
+        row.get::<usize, Option<String>>(7)?,
+        row.get::<usize, i64>(8)?,
+      ))
+    })?
+    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+  let mut entries = Vec::new();
+  for (id, project, user, tablename, recordid, action, before, after, changedate) in rows {
+    entries.push(AuditEntry {
+      id: id,
+      project: project,
+      user: user,
+      tablename: tablename,
+      recordid: recordid,
+      action: AuditAction::from_str(action.as_str())
+        .map_err(|_| format!("invalid audit action: {}", action))?,
+      before: match before {
+        Some(b) => Some(serde_json::from_str(b.as_str())?),
+        None => None,
+      },
+      after: match after {
+        Some(a) => Some(serde_json::from_str(a.as_str())?),
+        None => None,
+      },
+      changedate: changedate,
+    });
+  }
+  Ok(entries)
+}
+
+pub fn deleted_entries(
+  conn: &Connection,
+  projectid: i64,
+) -> Result<DeletedEntries, orgauth::error::Error> {
+  let mut pstmt =
+    conn.prepare("select id from timeentry where project = ?1 and deleted is not null")?;
+  let teids = pstmt
+    .query_map(params![projectid], |row| row.get(0))?
+    .collect::<Result<Vec<i64>, rusqlite::Error>>()?;
+  let mut pstmt =
+    conn.prepare("select id from allocation where project = ?1 and deleted is not null")?;
+  let aids = pstmt
+    .query_map(params![projectid], |row| row.get(0))?
+    .collect::<Result<Vec<i64>, rusqlite::Error>>()?;
+
+  Ok(DeletedEntries {
+    project: projectid,
+    timeentries: teids
+      .iter()
+      .map(|id| read_time_entry(conn, *id))
+      .collect::<Result<Vec<TimeEntry>, orgauth::error::Error>>()?,
+    allocations: aids
+      .iter()
+      .map(|id| read_allocation(conn, *id))
+      .collect::<Result<Vec<Allocation>, orgauth::error::Error>>()?,
+  })
+}
+
+// deleted time entries in 're' that are billed or before the project lock date.
+pub fn locked_restores(
+  conn: &Connection,
+  re: &RestoreEntries,
+) -> Result<Vec<LockedTimeEntry>, orgauth::error::Error> {
+  let mut locked = Vec::new();
+  for id in re.timeentries.iter() {
+    let te = read_time_entry(conn, *id)?;
+    if let Some(reason) = time_entry_lock(conn, te.startdate, te.project, te.billed)? {
+      locked.push(LockedTimeEntry {
+        id: Some(*id),
+        reason: reason,
+      });
+    }
+  }
+  Ok(locked)
+}
+
+// check for user membership before calling!
+// entries from other projects, or that aren't deleted, are left alone.
+// restores nothing if any time entry's (user, startdate) slot has been taken
+// by a live entry since it was deleted, or if a restored time entry fails
+// check_time_entry; returns those entry ids.
+pub fn restore_entries(
+  conn: &Connection,
+  uid: i64,
+  re: &RestoreEntries,
+) -> Result<Result<(), Vec<i64>>, orgauth::error::Error> {
+  let mut slots = HashSet::new();
+  let mut taken = Vec::new();
+  for id in re.timeentries.iter() {
+    match conn.query_row(
+      "select d.user, d.startdate,
+          exists (select * from timeentry te where
+            te.user = d.user and te.startdate = d.startdate and te.deleted is null)
+        from timeentry d where d.id = ?1 and d.project = ?2 and d.deleted is not null",
+      params![id, re.project],
+      |row| {
+        Ok((
+          row.get::<usize, i64>(0)?,
+          row.get::<usize, i64>(1)?,
+          row.get::<usize, bool>(2)?,
+        ))
+      },
+    ) {
+      // two restored entries can't share a slot either.
+      Ok((user, startdate, live)) => {
+        if live || !slots.insert((user, startdate)) {
+          taken.push(*id);
+        }
+      }
+      Err(rusqlite::Error::QueryReturnedNoRows) => (),
+      Err(e) => return Err(e.into()),
+    }
+  }
+  if !taken.is_empty() {
+    return Ok(Err(taken));
+  }
+
+  match all_or_nothing(conn, || match restore_items(conn, uid, re) {
+    Ok(failed) if failed.is_empty() => Ok(()),
+    r => Err(r),
+  })? {
+    Ok(()) => Ok(Ok(())),
+    Err(Ok(failed)) => Ok(Err(failed)),
+    Err(Err(e)) => Err(e),
+  }
+}
+
+// restore the entries in 're'.  returns the restored time entries that fail
+// checks, checked once they're all back so they're checked against each
+// other too.
+fn restore_items(
+  conn: &Connection,
+  uid: i64,
+  re: &RestoreEntries,
+) -> Result<Vec<i64>, orgauth::error::Error> {
+  let now = now()?;
+  let mut restored = Vec::new();
+  for id in re.timeentries.iter() {
+    if conn.execute(
+      "update timeentry set deleted = null, changeddate = ?1
+        where id = ?2 and project = ?3 and deleted is not null",
+      params![now, id, re.project],
+    )? > 0
+    {
+      restored.push(*id);
+      let after = read_time_entry(conn, *id)?;
+      insert_audit(
+        conn,
+        uid,
+        re.project,
+        "timeentry",
+        *id,
+        AuditAction::Restore,
+        None,
+        Some(&after),
+      )?;
+    }
+  }
+  for id in re.allocations.iter() {
+    if conn.execute(
+      "update allocation set deleted = null, changeddate = ?1
+        where id = ?2 and project = ?3 and deleted is not null",
+      params![now, id, re.project],
+    )? > 0
+    {
+      let after = read_allocation(conn, *id)?;
+      insert_audit(
+        conn,
+        uid,
+        re.project,
+        "allocation",
+        *id,
+        AuditAction::Restore,
+        None,
+        Some(&after),
+      )?;
+    }
+  }
+  let mut failed = Vec::new();
+  for id in restored {
+    let te = read_time_entry(conn, id)?;
+    if check_time_entry(conn, &resave(&te))?
+      .iter()
+      .any(|i| i.error)
+    {
+      failed.push(id);
+    }
+  }
+  Ok(failed)
+}
+
+// permanently remove entries deleted more than 'days' ago.  time entries that
+// are on an invoice are kept, so the invoice still lists them.
+pub fn purge_deleted(conn: &Connection, days: i64) -> Result<(), orgauth::error::Error> {
+  let cutoff = now()? - days * MILLIS_PER_DAY;
+  let purgeable = "select id from timeentry te where
+      te.deleted < ?1 and
+      not exists (select * from invoicetimeentry ite where ite.timeentry = te.id)";
+  conn.execute(
+    format!(
+      "delete from timeentrytag where timeentry in ({})",
+      purgeable
+    )
+    .as_str(),
+    params![cutoff],
+  )?;
+  conn.execute(
+    format!("delete from timeentry where id in ({})", purgeable).as_str(),
+    params![cutoff],
+  )?;
+  conn.execute("delete from allocation where deleted < ?1", params![cutoff])?;
+  Ok(())
+}
+
+// a save is stale when the client saw an older changeddate than the current one.
+fn is_stale(expected: Option<i64>, current: i64) -> bool {
+  match expected {
+    Some(cd) => cd != current,
+    None => false,
+  }
+}
+
+pub fn project_time_conflicts(
+  conn: &Connection,
+  spt: &SaveProjectTime,
+) -> Result<ProjectTimeConflicts, orgauth::error::Error> {
+  let mut conflicts = ProjectTimeConflicts {
+    timeentries: Vec::new(),
+    payentries: Vec::new(),
+    allocations: Vec::new(),
+  };
+  // entries from other projects aren't reported; saving them fails.
+  let saved = spt.savetimeentries.iter().map(|s| (s.id, s.changeddate));
+  let deleted = spt
+    .deletetimeentries
+    .iter()
+    .map(|d| (Some(d.id()), d.changeddate()));
+  for (id, changeddate) in saved.chain(deleted) {
+    if let (Some(id), Some(_)) = (id, changeddate) {
+      let te = read_time_entry(conn, id)?;
+      if te.project == spt.project && is_stale(changeddate, te.changeddate) {
+        conflicts.timeentries.push(te);
+      }
+    }
+  }
+  let saved = spt.savepayentries.iter().map(|s| (s.id, s.changeddate));
+  let deleted = spt
+    .deletepayentries
+    .iter()
+    .map(|d| (Some(d.id()), d.changeddate()));
+  for (id, changeddate) in saved.chain(deleted) {
+    if let (Some(id), Some(_)) = (id, changeddate) {
+      let pe = read_pay_entry(conn, id)?;
+      if pe.project == spt.project && is_stale(changeddate, pe.changeddate) {
+        conflicts.payentries.push(pe);
+      }
+    }
+  }
+  let saved = spt.saveallocations.iter().map(|s| (s.id, s.changeddate));
+  let deleted = spt
+    .deleteallocations
+    .iter()
+    .map(|d| (Some(d.id()), d.changeddate()));
+  for (id, changeddate) in saved.chain(deleted) {
+    if let (Some(id), Some(_)) = (id, changeddate) {
+      let a = read_allocation(conn, id)?;
+      if a.project == spt.project && is_stale(changeddate, a.changeddate) {
+        conflicts.allocations.push(a);
+      }
+    }
+  }
+  Ok(conflicts)
+}
+
+// the project as it is now, if 'sp' was made over a newer change.
+pub fn project_edit_conflict(
+  conn: &Connection,
+  sp: &SaveProjectEdit,
+) -> Result<Option<ProjectEdit>, orgauth::error::Error> {
+  match sp.project.id {
+    Some(pid) => {
+      if is_stale(sp.project.changeddate, read_project(conn, pid)?.changeddate) {
+        Ok(Some(read_project_edit(conn, pid)?))
+      } else {
+        Ok(None)
+      }
+    }
+    None => Ok(None),
+  }
+}
+
+// run 'f', keeping its changes only if it succeeds.  uses a savepoint rather
+// than a transaction, so it also works inside a caller's transaction.
+fn all_or_nothing<T, E, F>(conn: &Connection, f: F) -> Result<Result<T, E>, orgauth::error::Error>
+where
+  F: FnOnce() -> Result<T, E>,
+{
+  conn.execute_batch("savepoint allornothing")?;
+  let r = f();
+  match r {
+    Ok(_) => conn.execute_batch("release allornothing")?,
+    Err(_) => conn.execute_batch("rollback to allornothing; release allornothing")?,
+  }
+  Ok(r)
+}
+
+// check for admin role before calling!
+pub fn set_project_overlaps(
+  conn: &Connection,
+  project: i64,
+  reject: bool,
+) -> Result<Project, orgauth::error::Error> {
+  let now = now()?;
+  conn.execute(
+    "update project set reject_overlaps = ?1, changeddate = ?2 where id = ?3",
+    params![reject, now, project],
+  )?;
+  read_project(conn, project)
+}
+
+// project entries that overlap other entries for the same user.  overlaps
+// within the project are listed once.
+// the other entry and its project are left out unless 'uid' is a member of
+// that project.
+pub fn find_overlaps(
+  conn: &Connection,
+  uid: i64,
+  projectid: i64,
+) -> Result<OverlapReport, orgauth::error::Error> {
+  let mut pstmt = conn.prepare(
+    "select a.user, a.id, m.project is not null, b.id, b.project,
+        max(a.startdate, b.startdate), min(a.enddate, b.enddate)
+      from timeentry a, timeentry b
+        left join projectmember m on m.project = b.project and m.user = ?2
+      where
+        a.project = ?1 and
+        b.user = a.user and
+        b.id != a.id and
+        (b.project != a.project or a.id < b.id) and
+        a.deleted is null and b.deleted is null and
+        a.ignore = 0 and b.ignore = 0 and
+        a.startdate < b.enddate and
+        b.startdate < a.enddate
+      order by a.user, a.startdate",
+  )?;
+  let overlaps = pstmt
+    .query_map(params![projectid, uid], |row| {
+      let member: bool = row.get(2)?;
+      Ok(Overlap {
+        user: row.get(0)?,
+        entry: row.get(1)?,
+        other: if member { row.get(3)? } else { None },
+        otherproject: if member { row.get(4)? } else { None },
+        startdate: row.get(5)?,
+        enddate: row.get(6)?,
+      })
+    })?
+    .collect::<Result<Vec<Overlap>, rusqlite::Error>>()?;
+  Ok(OverlapReport {
+    project: projectid,
+    overlaps: overlaps,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::data::{DeleteEntry, DistributionStrategy, ExtraField, SaveProjectMember};
+  use rusqlite::{Transaction, TransactionBehavior};
+
+  // a fresh database with one user and a project they admin.
+  fn testdb() -> (Connection, i64, i64) {
+    let (_, conn, uid, pid) = testdb_file();
+    (conn, uid, pid)
+  }
+
+  fn testdb_file() -> (std::path::PathBuf, Connection, i64, i64) {
+    let dbfile = std::env::temp_dir().join(format!("timeclonk-test-{}.db", Uuid::new_v4()));
+    dbinit(dbfile.as_path(), None).unwrap();
+    let conn = connection_open(dbfile.as_path()).unwrap();
+    let rd = RegistrationData {
+      uid: "test".to_string(),
+      pwd: "test".to_string(),
+      email: "".to_string(),
+    };
+    let mut cb = timeclonk_callbacks();
+    orgauth::dbfun::new_user(&conn, &rd, None, None, true, None, &mut cb.on_new_user).unwrap();
+    let uid = orgauth::dbfun::read_user_by_name(&conn, "test").unwrap().id;
+    let pid = test_project(&conn, uid);
+    (dbfile, conn, uid, pid)
+  }
+
+  fn test_project(conn: &Connection, uid: i64) -> i64 {
+    save_project(conn, uid, test_save_project(None)).unwrap().id
+  }
+
+  fn test_save_project(id: Option<i64>) -> SaveProject {
+    SaveProject {
+      id: id,
+      name: "test".to_string(),
+      description: Some("".to_string()),
+      due_days: None,
+      extra_fields: Vec::new(),
+      invoice_id_template: "".to_string(),
+      invoice_seq: 0,
+      payer: "".to_string(),
+      payee: "".to_string(),
+      generic_task: "".to_string(),
+      public: false,
+      rate: None,
+      currency: None,
+      distribution: None,
+      changeddate: None,
+    }
+  }
+
+  // a new user, added to the project with 'role'.
+  fn test_member(conn: &Connection, uid: i64, pid: i64, name: &str, role: Role) -> i64 {
+    let rd = RegistrationData {
+      uid: name.to_string(),
+      pwd: "test".to_string(),
+      email: "".to_string(),
+    };
+    let mut cb = timeclonk_callbacks();
+    let mid =
+      orgauth::dbfun::new_user(&conn, &rd, None, None, true, None, &mut cb.on_new_user).unwrap();
+    edit_members(conn, uid, pid, vec![member(mid, role, None)]);
+    mid
+  }
+
+  fn member(id: i64, role: Role, rate: Option<f64>) -> SaveProjectMember {
+    SaveProjectMember {
+      id: id,
+      delete: false,
+      role: role,
+      rate: rate,
+      clear_rate: false,
+    }
+  }
+
+  fn edit_members(
+    conn: &Connection,
+    uid: i64,
+    pid: i64,
+    members: Vec<SaveProjectMember>,
+  ) -> SavedProjectEdit {
+    save_project_edit(
+      conn,
+      uid,
+      SaveProjectEdit {
+        project: test_save_project(Some(pid)),
+        members: members,
+      },
+    )
+    .unwrap()
+  }
+
+  fn test_entry(conn: &Connection, uid: i64, pid: i64, startdate: i64) -> i64 {
+    let ste = SaveTimeEntry {
+      id: None,
+      project: pid,
+      user: uid,
+      description: "work".to_string(),
+      startdate: startdate,
+      enddate: startdate + MILLIS_PER_HOUR,
+      ignore: false,
+      tags: None,
+      task: None,
+      clear_task: false,
+      changeddate: None,
+    };
+    save_time_entry(conn, uid, ste, false).unwrap().unwrap().0
+  }
+
+  fn test_allocation(conn: &Connection, uid: i64, pid: i64, duration: i64) -> i64 {
+    save_allocation(
+      conn,
+      uid,
+      SaveAllocation {
+        id: None,
+        project: pid,
+        duration: duration,
+        allocationdate: now().unwrap() - 2 * MILLIS_PER_DAY,
+        description: "allocation".to_string(),
+        changeddate: None,
+      },
+    )
+    .unwrap()
+  }
+
+  fn delete_batch(project: i64, teid: DeleteEntry) -> SaveProjectTime {
+    SaveProjectTime {
+      project: project,
+      savetimeentries: Vec::new(),
+      deletetimeentries: vec![teid],
+      savepayentries: Vec::new(),
+      deletepayentries: Vec::new(),
+      saveallocations: Vec::new(),
+      deleteallocations: Vec::new(),
+      unlock: None,
+    }
+  }
+
+  #[test]
+  fn project_time_scope() {
+    let (conn, uid, pid) = testdb();
+    let other = test_project(&conn, uid);
+    let te = test_entry(&conn, uid, other, now().unwrap() - MILLIS_PER_DAY);
+    // the entry isn't in 'pid', so it can't be deleted through it.
+    let spt = delete_batch(pid, DeleteEntry::Id(te));
+    assert!(project_time_conflicts(&conn, &spt).unwrap().is_empty());
+    assert!(save_project_time(&conn, uid, spt, false).unwrap().is_err());
+    assert!(read_time_entry(&conn, te).is_ok());
+    assert_eq!(time_entries(&conn, other).unwrap().len(), 1);
+  }
+
+  #[test]
+  fn stale_delete() {
+    let (conn, uid, pid) = testdb();
+    let te = test_entry(&conn, uid, pid, now().unwrap() - MILLIS_PER_DAY);
+    let changeddate = read_time_entry(&conn, te).unwrap().changeddate;
+    let spt = delete_batch(
+      pid,
+      DeleteEntry::Seen {
+        id: te,
+        changeddate: changeddate - 1,
+      },
+    );
+    assert_eq!(
+      project_time_conflicts(&conn, &spt)
+        .unwrap()
+        .timeentries
+        .len(),
+      1
+    );
+    let spt = delete_batch(
+      pid,
+      DeleteEntry::Seen {
+        id: te,
+        changeddate: changeddate,
+      },
+    );
+    assert!(project_time_conflicts(&conn, &spt).unwrap().is_empty());
+  }
+
+  #[test]
+  fn reimport() {
+    let (dbfile, conn, uid, pid) = testdb_file();
+    let te = test_entry(&conn, uid, pid, now().unwrap() - MILLIS_PER_DAY);
+    let export = export_db(&dbfile).unwrap();
+
+    // importing into the same db finds the project and its entry.
+    let report = import_db(&dbfile, export.clone(), false).unwrap();
+    assert_eq!(report.projects, 0);
+    assert_eq!(report.existingprojects, 1);
+    assert_eq!(report.skipped, 1);
+    assert!(report.conflicts.is_empty());
+    assert_eq!(project_ids(&conn).unwrap().len(), 1);
+
+    // a different entry in the same slot is a conflict.
+    conn
+      .execute(
+        "update timeentry set enddate = enddate + 1 where id = ?1",
+        params![te],
+      )
+      .unwrap();
+    let report = import_db(&dbfile, export, false).unwrap();
+    assert_eq!(report.skipped, 0);
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(time_entries(&conn, pid).unwrap().len(), 1);
+  }
+
+  #[test]
+  fn export_invoices_rates_deleted() {
+    let (dbfile, conn, uid, pid) = testdb_file();
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let billed = test_entry(&conn, uid, pid, start);
+    let gone = test_entry(&conn, uid, pid, start + MILLIS_PER_HOUR);
+    delete_time_entry(&conn, uid, gone, false).unwrap();
+    record_rate(&conn, uid, pid, Some(uid), Some(10.0), start).unwrap();
+    save_invoice(
+      &conn,
+      uid,
+      pid,
+      &PrintInvoice {
+        id: "INV-1".to_string(),
+        payer: "payer".to_string(),
+        payee: "payee".to_string(),
+        items: Vec::new(),
+        date: "2024-01-01".to_string(),
+        due_date: None,
+        extra_fields: Vec::new(),
+        currency: "$".to_string(),
+        project: Some(pid),
+        timeentries: Some(vec![billed]),
+      },
+    )
+    .unwrap();
+    // through json, as --export and --import do.
+    let json = serde_json::to_string(&export_db(&dbfile).unwrap()).unwrap();
+    let export: Export = serde_json::from_str(json.as_str()).unwrap();
+    let rates = project_rate_history(&conn, pid).unwrap().len();
+
+    let (dbfile2, conn2, _, _) = testdb_file();
+    let report = import_db(&dbfile2, export.clone(), false).unwrap();
+    assert_eq!(report.projects, 1);
+    assert_eq!(report.timeentries, 1);
+    assert_eq!(report.deleted, 1);
+    assert_eq!(report.invoices, 1);
+    assert_eq!(report.rates as usize, rates);
+    let pid2 = *project_ids(&conn2).unwrap().last().unwrap();
+    assert_ne!(pid2, pid);
+    assert!(time_entries(&conn2, pid2).unwrap()[0].billed);
+    assert_eq!(deleted_entries(&conn2, pid2).unwrap().timeentries.len(), 1);
+    assert_eq!(project_rate_history(&conn2, pid2).unwrap().len(), rates);
+
+    // importing again skips it all.
+    let report = import_db(&dbfile2, export.clone(), false).unwrap();
+    assert_eq!(
+      report.timeentries + report.deleted + report.invoices + report.rates,
+      0
+    );
+    assert!(report.conflicts.is_empty());
+
+    let mut unknown = export;
+    unknown.version = EXPORT_VERSION + 1;
+    assert!(import_db(&dbfile2, unknown, false).is_err());
+  }
+
+  #[test]
+  fn export_allocations_once() {
+    let (dbfile, conn, uid, pid) = testdb_file();
+    test_member(&conn, uid, pid, "member", Role::Member);
+    test_member(&conn, uid, pid, "observer", Role::Observer);
+    test_allocation(&conn, uid, pid, MILLIS_PER_HOUR);
+    let export = export_db(&dbfile).unwrap();
+    assert_eq!(export.projects[0].time.members.len(), 3);
+    assert_eq!(export.projects[0].time.allocations.len(), 1);
+  }
+
+  #[test]
+  fn import_new_users() {
+    let (dbfile, conn, uid, pid) = testdb_file();
+    let mid = test_member(&conn, uid, pid, "member", Role::Member);
+    test_entry(&conn, mid, pid, now().unwrap() - MILLIS_PER_DAY);
+    test_allocation(&conn, uid, pid, MILLIS_PER_HOUR);
+    let export = export_db(&dbfile).unwrap();
+
+    let (dbfile2, conn2, _, _) = testdb_file();
+    assert!(import_db(&dbfile2, export.clone(), false).is_err());
+    let report = import_db(&dbfile2, export, true).unwrap();
+    assert_eq!(report.newusers, vec!["member".to_string()]);
+    assert_eq!(report.timeentries, 1);
+    let pid2 = *project_ids(&conn2).unwrap().last().unwrap();
+    let mid2 = orgauth::dbfun::read_user_by_name(&conn2, "member")
+      .unwrap()
+      .id;
+    assert_eq!(time_entries(&conn2, pid2).unwrap()[0].user, mid2);
+    // imported rows are in the audit log.
+    let mut audited: Vec<(String, String)> = project_audit(&conn2, pid2)
+      .unwrap()
+      .into_iter()
+      .map(|a| (a.tablename, a.action.to_string()))
+      .collect();
+    audited.sort();
+    assert_eq!(
+      audited,
+      vec![
+        ("allocation".to_string(), "Import".to_string()),
+        ("timeentry".to_string(), "Import".to_string())
+      ]
+    );
+  }
+
+  #[test]
+  fn locked_entries() {
+    let (conn, uid, pid) = testdb();
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let id = test_entry(&conn, uid, pid, start);
+    clock_in(
+      &conn,
+      uid,
+      ClockIn {
+        project: pid,
+        description: "timer".to_string(),
+      },
+    )
+    .unwrap();
+    set_project_lock(&conn, pid, Some(now().unwrap() + MILLIS_PER_DAY)).unwrap();
+
+    let mut ste = SaveTimeEntry {
+      id: Some(id),
+      project: pid,
+      user: uid,
+      description: "changed".to_string(),
+      startdate: start,
+      enddate: start + MILLIS_PER_HOUR,
+      ignore: false,
+      tags: None,
+      task: None,
+      clear_task: false,
+      changeddate: None,
+    };
+    assert!(save_time_entry(&conn, uid, ste.clone(), false).is_err());
+    assert!(delete_time_entry(&conn, uid, id, false).is_err());
+    assert!(clock_out(&conn, uid, ClockOut { description: None }).is_err());
+    // and the timer is still running, in clock in too.
+    assert!(active_timer(&conn, uid).unwrap().is_some());
+    assert!(clock_in(
+      &conn,
+      uid,
+      ClockIn {
+        project: pid,
+        description: "next".to_string(),
+      }
+    )
+    .is_err());
+    assert_eq!(
+      active_timer(&conn, uid).unwrap().unwrap().description,
+      "timer"
+    );
+
+    ste.id = None;
+    ste.startdate = start - MILLIS_PER_DAY;
+    ste.enddate = ste.startdate + MILLIS_PER_HOUR;
+    assert!(save_time_entry(&conn, uid, ste, true).unwrap().is_ok());
+    delete_time_entry(&conn, uid, id, true).unwrap();
+  }
+
+  #[test]
+  fn invoice_day_rate() {
+    let (conn, uid, pid) = testdb();
+    let day = now().unwrap().div_euclid(MILLIS_PER_DAY) - 2;
+    let noon = day * MILLIS_PER_DAY + 12 * MILLIS_PER_HOUR;
+    record_rate(&conn, uid, pid, None, Some(10.0), day * MILLIS_PER_DAY).unwrap();
+    record_rate(&conn, uid, pid, None, Some(20.0), noon).unwrap();
+    test_entry(&conn, uid, pid, noon + MILLIS_PER_HOUR);
+    // the rate at the start of the day, for invoices and distributions alike.
+    let it = invoice_time(&conn, pid, false).unwrap();
+    assert_eq!(it.items[0].rate, 10.0);
+    let d = crate::distribution::project_distribution(
+      &conn,
+      crate::data::CalcDistribution {
+        project: pid,
+        strategy: None,
+        hours: Some(1.0),
+      },
+    )
+    .unwrap();
+    assert_eq!(d.users[0].amount, Some(10.0));
+  }
+
+  #[test]
+  fn forecast_many_members() {
+    let (conn, uid, pid) = testdb();
+    test_member(&conn, uid, pid, "member", Role::Member);
+    test_member(&conn, uid, pid, "observer", Role::Observer);
+    test_allocation(&conn, uid, pid, 10 * MILLIS_PER_HOUR);
+    test_entry(&conn, uid, pid, now().unwrap() - MILLIS_PER_DAY);
+    let f = crate::forecast::project_forecast(
+      &conn,
+      crate::data::GetForecast {
+        project: pid,
+        weeks: None,
+      },
+    )
+    .unwrap();
+    assert_eq!(f.allocated, 10 * MILLIS_PER_HOUR);
+    assert_eq!(f.remaining, 9 * MILLIS_PER_HOUR);
+    assert_eq!(
+      f.burndown
+        .iter()
+        .map(|bp| bp.remaining)
+        .collect::<Vec<i64>>(),
+      vec![10 * MILLIS_PER_HOUR, 9 * MILLIS_PER_HOUR]
+    );
+  }
+
+  #[test]
+  fn alert_many_members() {
+    let (conn, uid, pid) = testdb();
+    test_member(&conn, uid, pid, "member", Role::Member);
+    test_member(&conn, uid, pid, "observer", Role::Observer);
+    test_allocation(&conn, uid, pid, 2 * MILLIS_PER_HOUR);
+    set_project_alert(&conn, pid, Some(MILLIS_PER_HOUR)).unwrap();
+    let low = |conn: &Connection| {
+      crate::alerts::low_allocation(conn, &read_project(conn, pid).unwrap()).unwrap()
+    };
+    assert!(low(&conn).is_none());
+    // down to the threshold.
+    test_entry(&conn, uid, pid, now().unwrap() - MILLIS_PER_DAY);
+    assert_eq!(low(&conn).unwrap().remaining, MILLIS_PER_HOUR);
+  }
+
+  #[test]
+  fn distribute_allocation_many_members() {
+    let (conn, uid, pid) = testdb();
+    let mid = test_member(&conn, uid, pid, "member", Role::Member);
+    test_member(&conn, uid, pid, "observer", Role::Observer);
+    test_allocation(&conn, uid, pid, MILLIS_PER_HOUR);
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    test_entry(&conn, uid, pid, start);
+    test_entry(&conn, mid, pid, start);
+    // the unpaid allocation, counted once.
+    let d = crate::distribution::project_distribution(
+      &conn,
+      crate::data::CalcDistribution {
+        project: pid,
+        strategy: Some(DistributionStrategy::EqualSplit),
+        hours: None,
+      },
+    )
+    .unwrap();
+    assert_eq!(d.duration, MILLIS_PER_HOUR);
+  }
+
+  #[test]
+  fn alerts_sent_per_admin() {
+    let (conn, uid, pid) = testdb();
+    conn
+      .execute(
+        "update orgauth_user set email = 'test@example.com' where id = ?1",
+        params![uid],
+      )
+      .unwrap();
+    set_project_alert(&conn, pid, Some(MILLIS_PER_HOUR)).unwrap();
+    let sent = |conn: &Connection| alert_recipients(conn, pid).unwrap()[0].2;
+    assert!(!sent(&conn));
+    set_alert_sent(&conn, pid, uid).unwrap();
+    assert!(sent(&conn));
+    // a new threshold gets a fresh alert.
+    set_project_alert(&conn, pid, Some(2 * MILLIS_PER_HOUR)).unwrap();
+    assert!(!sent(&conn));
+  }
+
+  #[test]
+  fn time_entry_checks() {
+    let (conn, uid, pid) = testdb();
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let checks = |ste: &SaveTimeEntry| -> Vec<(TimeEntryCheck, bool)> {
+      check_time_entry(&conn, ste)
+        .unwrap()
+        .into_iter()
+        .map(|i| (i.check, i.error))
+        .collect()
+    };
+    let mut ste = SaveTimeEntry {
+      id: None,
+      project: pid,
+      user: uid,
+      description: "work".to_string(),
+      startdate: start,
+      enddate: start + MILLIS_PER_HOUR,
+      ignore: false,
+      tags: None,
+      task: None,
+      clear_task: false,
+      changeddate: None,
+    };
+    assert_eq!(checks(&ste), vec![]);
+
+    ste.enddate = start - 1;
+    assert_eq!(checks(&ste), vec![(TimeEntryCheck::EndBeforeStart, true)]);
+    ste.enddate = start;
+    assert_eq!(checks(&ste), vec![(TimeEntryCheck::ZeroLength, false)]);
+    ste.enddate = now().unwrap() + 400 * MILLIS_PER_DAY;
+    assert_eq!(checks(&ste), vec![(TimeEntryCheck::FarFuture, false)]);
+
+    // overlaps warn, or are errors if the project says so.  ignored entries
+    // don't overlap.
+    test_entry(&conn, uid, pid, start + MILLIS_PER_HOUR / 2);
+    ste.enddate = start + MILLIS_PER_HOUR;
+    assert_eq!(checks(&ste), vec![(TimeEntryCheck::Overlap, false)]);
+    set_project_overlaps(&conn, pid, true).unwrap();
+    assert_eq!(checks(&ste), vec![(TimeEntryCheck::Overlap, true)]);
+    ste.ignore = true;
+    assert_eq!(checks(&ste), vec![]);
+  }
+
+  #[test]
+  fn bad_distribution() {
+    let (conn, _, pid) = testdb();
+    conn
+      .execute(
+        "update project set distribution = 'nonsense' where id = ?1",
+        params![pid],
+      )
+      .unwrap();
+    assert!(read_project(&conn, pid).is_err());
+    conn
+      .execute(
+        "update project set distribution = null where id = ?1",
+        params![pid],
+      )
+      .unwrap();
+    assert!(matches!(
+      read_project(&conn, pid).unwrap().distribution,
+      DistributionStrategy::WorkedFirst
+    ));
+  }
+
+  #[test]
+  fn member_time_current_projects() {
+    let (conn, uid, pid) = testdb();
+    let other = test_project(&conn, uid);
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    test_entry(&conn, uid, pid, start);
+    test_entry(&conn, uid, other, start + MILLIS_PER_HOUR);
+    assert_eq!(member_time(&conn, uid).unwrap().len(), 2);
+    conn
+      .execute(
+        "delete from projectmember where project = ?1",
+        params![other],
+      )
+      .unwrap();
+    let time = member_time(&conn, uid).unwrap();
+    assert_eq!(time.len(), 1);
+    assert_eq!(time[0].project, pid);
+  }
+
+  #[test]
+  fn move_tags() {
+    let (conn, uid, pid) = testdb();
+    let other = test_project(&conn, uid);
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let mut ste = SaveTimeEntry {
+      id: None,
+      project: pid,
+      user: uid,
+      description: "work".to_string(),
+      startdate: start,
+      enddate: start + MILLIS_PER_HOUR,
+      ignore: false,
+      tags: Some(vec!["design".to_string()]),
+      task: None,
+      clear_task: false,
+      changeddate: None,
+    };
+    let id = save_time_entry(&conn, uid, ste.clone(), false)
+      .unwrap()
+      .unwrap()
+      .0;
+    ste.id = Some(id);
+    ste.project = other;
+    ste.tags = None;
+    save_time_entry(&conn, uid, ste, false).unwrap().unwrap();
+    let tagproject: i64 = conn
+      .query_row(
+        "select t.project from timeentrytag tt, projecttag t where tt.tag = t.id and tt.timeentry = ?1",
+        params![id],
+        |row| row.get(0),
+      )
+      .unwrap();
+    assert_eq!(tagproject, other);
+    assert_eq!(read_time_entry(&conn, id).unwrap().tags, vec!["design"]);
+  }
+
+  #[test]
+  fn delete_task_locked() {
+    let (conn, uid, pid) = testdb();
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let task = save_task(
+      &conn,
+      uid,
+      SaveTask {
+        id: None,
+        project: pid,
+        name: "task".to_string(),
+        estimate: None,
+        status: TaskStatus::Open,
+        assignee: None,
+      },
+    )
+    .unwrap()
+    .id;
+    let mut ste = resave(&read_time_entry(&conn, test_entry(&conn, uid, pid, start)).unwrap());
+    ste.task = Some(task);
+    let teid = save_time_entry(&conn, uid, ste, false).unwrap().unwrap().0;
+    // entries before the lock date keep their task.
+    set_project_lock(&conn, pid, Some(start + MILLIS_PER_HOUR)).unwrap();
+    assert_eq!(
+      delete_task(&conn, uid, pid, task)
+        .unwrap()
+        .unwrap_err()
+        .len(),
+      1
+    );
+    assert_eq!(read_time_entry(&conn, teid).unwrap().task, Some(task));
+    set_project_lock(&conn, pid, None).unwrap();
+    delete_task(&conn, uid, pid, task).unwrap().unwrap();
+    assert_eq!(read_time_entry(&conn, teid).unwrap().task, None);
+    assert!(tasks(&conn, pid).unwrap().is_empty());
+    let audit = project_audit(&conn, pid).unwrap();
+    assert_eq!(
+      (audit[0].recordid, audit[0].action.to_string()),
+      (teid, "Update".to_string())
+    );
+  }
+
+  #[test]
+  fn keep_task() {
+    let (conn, uid, pid) = testdb();
+    let task = save_task(
+      &conn,
+      uid,
+      SaveTask {
+        id: None,
+        project: pid,
+        name: "task".to_string(),
+        estimate: None,
+        status: TaskStatus::Open,
+        assignee: None,
+      },
+    )
+    .unwrap();
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let id = test_entry(&conn, uid, pid, start);
+    let mut ste = SaveTimeEntry {
+      id: Some(id),
+      project: pid,
+      user: uid,
+      description: "work".to_string(),
+      startdate: start,
+      enddate: start + MILLIS_PER_HOUR,
+      ignore: false,
+      tags: None,
+      task: Some(task.id),
+      clear_task: false,
+      changeddate: None,
+    };
+    save_time_entry(&conn, uid, ste.clone(), false)
+      .unwrap()
+      .unwrap();
+    assert_eq!(read_time_entry(&conn, id).unwrap().task, Some(task.id));
+    // no task leaves it as it is.
+    ste.task = None;
+    save_time_entry(&conn, uid, ste.clone(), false)
+      .unwrap()
+      .unwrap();
+    assert_eq!(read_time_entry(&conn, id).unwrap().task, Some(task.id));
+    ste.clear_task = true;
+    save_time_entry(&conn, uid, ste, false).unwrap().unwrap();
+    assert_eq!(read_time_entry(&conn, id).unwrap().task, None);
+  }
+
+  #[test]
+  fn restore_taken_slot() {
+    let (conn, uid, pid) = testdb();
+    let start = now().unwrap() - 2 * MILLIS_PER_DAY;
+    let a = test_entry(&conn, uid, pid, start);
+    delete_time_entry(&conn, uid, a, false).unwrap();
+    // the deleted entry doesn't hold its start time.
+    let b = test_entry(&conn, uid, pid, start);
+    let re = RestoreEntries {
+      project: pid,
+      timeentries: vec![a],
+      allocations: Vec::new(),
+      unlock: None,
+    };
+    assert_eq!(restore_entries(&conn, uid, &re).unwrap(), Err(vec![a]));
+    delete_time_entry(&conn, uid, b, false).unwrap();
+    assert_eq!(restore_entries(&conn, uid, &re).unwrap(), Ok(()));
+    assert!(read_time_entry(&conn, a).is_ok());
+  }
+
+  #[test]
+  fn restore_checked() {
+    let (conn, uid, pid) = testdb();
+    set_project_overlaps(&conn, pid, true).unwrap();
+    let start = now().unwrap() - 2 * MILLIS_PER_DAY;
+    let a = test_entry(&conn, uid, pid, start);
+    delete_time_entry(&conn, uid, a, false).unwrap();
+    test_entry(&conn, uid, pid, start + MILLIS_PER_HOUR / 2);
+    let re = RestoreEntries {
+      project: pid,
+      timeentries: vec![a],
+      allocations: Vec::new(),
+      unlock: None,
+    };
+    // overlaps are errors in this project, so it stays deleted.
+    assert_eq!(restore_entries(&conn, uid, &re).unwrap(), Err(vec![a]));
+    assert_eq!(deleted_entries(&conn, pid).unwrap().timeentries.len(), 1);
+  }
+
+  #[test]
+  fn overlaps_redacted() {
+    let (conn, uid, pid) = testdb();
+    let other = test_project(&conn, uid);
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    test_entry(&conn, uid, pid, start);
+    let b = test_entry(&conn, uid, other, start + MILLIS_PER_HOUR / 2);
+    let o = &find_overlaps(&conn, uid, pid).unwrap().overlaps[0];
+    assert_eq!((o.other, o.otherproject), (Some(b), Some(other)));
+    conn
+      .execute(
+        "delete from projectmember where project = ?1",
+        params![other],
+      )
+      .unwrap();
+    let o = &find_overlaps(&conn, uid, pid).unwrap().overlaps[0];
+    assert_eq!((o.other, o.otherproject), (None, None));
+  }
+
+  #[test]
+  fn member_rate_kept() {
+    let (conn, uid, pid) = testdb();
+    let mid = test_member(&conn, uid, pid, "member", Role::Member);
+    edit_members(&conn, uid, pid, vec![member(mid, Role::Member, Some(20.0))]);
+    let history = || project_rate_history(&conn, pid).unwrap().len();
+    let rate = || {
+      member_list(&conn, pid)
+        .unwrap()
+        .into_iter()
+        .find(|m| m.id == mid)
+        .unwrap()
+        .rate
+    };
+    let changes = history();
+    // a role change without a rate, as the web client sends it.
+    edit_members(&conn, uid, pid, vec![member(mid, Role::Admin, None)]);
+    assert_eq!(rate(), Some(20.0));
+    assert_eq!(history(), changes);
+    let mut m = member(mid, Role::Admin, None);
+    m.clear_rate = true;
+    edit_members(&conn, uid, pid, vec![m]);
+    assert_eq!(rate(), None);
+    assert_eq!(history(), changes + 1);
+  }
+
+  #[test]
+  fn stale_project_edit() {
+    let (conn, uid, pid) = testdb();
+    let seen = read_project(&conn, pid).unwrap().changeddate;
+    let mut sp = SaveProjectEdit {
+      project: test_save_project(Some(pid)),
+      members: Vec::new(),
+    };
+    sp.project.changeddate = Some(seen);
+    // checked and saved in one transaction, as the SaveProjectEdit handler does.
+    let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate).unwrap();
+    assert!(project_edit_conflict(&tx, &sp).unwrap().is_none());
+    save_project_edit(&tx, uid, sp.clone()).unwrap();
+    tx.commit().unwrap();
+    // an edit made from an older changeddate is a conflict.
+    sp.project.changeddate = Some(seen - 1);
+    assert!(project_edit_conflict(&conn, &sp).unwrap().is_some());
+  }
+
+  #[test]
+  fn remove_member() {
+    let (conn, uid, pid) = testdb();
+    let mid = test_member(&conn, uid, pid, "member", Role::Member);
+    assert_eq!(member_list(&conn, pid).unwrap().len(), 2);
+    let mut m = member(mid, Role::Member, None);
+    m.delete = true;
+    let saved = edit_members(&conn, uid, pid, vec![m]);
+    assert_eq!(saved.members.len(), 1);
+    assert_eq!(saved.members[0].id, uid);
+  }
+
+  #[test]
+  fn clock_in_out() {
+    let (conn, uid, pid) = testdb();
+    clock_in(
+      &conn,
+      uid,
+      ClockIn {
+        project: pid,
+        description: "work".to_string(),
+      },
+    )
+    .unwrap();
+    let timers = active_timers(&conn, uid).unwrap();
+    assert_eq!(timers.len(), 1);
+    assert_eq!(timers[0].project, pid);
+    let te = clock_out(
+      &conn,
+      uid,
+      ClockOut {
+        description: Some("done".to_string()),
+      },
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(te.description, "done");
+    assert_eq!(te.startdate, timers[0].startdate);
+    assert!(active_timers(&conn, uid).unwrap().is_empty());
+    // nothing running, nothing to clock out.
+    assert!(clock_out(&conn, uid, ClockOut { description: None })
+      .unwrap()
+      .is_none());
+  }
+
+  #[test]
+  fn invoice_status() {
+    let (conn, uid, pid) = testdb();
+    let mut pi = PrintInvoice {
+      id: "INV-1".to_string(),
+      payer: "payer".to_string(),
+      payee: "payee".to_string(),
+      items: Vec::new(),
+      date: "2024-01-01".to_string(),
+      due_date: None,
+      extra_fields: Vec::new(),
+      currency: "$".to_string(),
+      project: Some(pid),
+      timeentries: None,
+    };
+    let id = save_invoice(&conn, uid, pid, &pi).unwrap();
+    set_invoice_status(&conn, id, InvoiceStatus::Sent).unwrap();
+    assert_eq!(
+      read_invoice(&conn, id).unwrap().unwrap().status,
+      InvoiceStatus::Sent
+    );
+    // sent invoices aren't changed by printing them again.
+    pi.payer = "other".to_string();
+    save_invoice(&conn, uid, pid, &pi).unwrap();
+    let invs = invoices(&conn, pid).unwrap();
+    assert_eq!(invs.len(), 1);
+    assert_eq!(invs[0].payer, "payer");
+  }
+
+  #[test]
+  fn invoice_time_unbilled() {
+    let (conn, uid, pid) = testdb();
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let billed = test_entry(&conn, uid, pid, start);
+    let unbilled = test_entry(&conn, uid, pid, start + MILLIS_PER_HOUR);
+    save_invoice(
+      &conn,
+      uid,
+      pid,
+      &PrintInvoice {
+        id: "INV-1".to_string(),
+        payer: "payer".to_string(),
+        payee: "payee".to_string(),
+        items: Vec::new(),
+        date: "2024-01-01".to_string(),
+        due_date: None,
+        extra_fields: Vec::new(),
+        currency: "$".to_string(),
+        project: Some(pid),
+        timeentries: Some(vec![billed]),
+      },
+    )
+    .unwrap();
+    assert!(read_time_entry(&conn, billed).unwrap().billed);
+    let ids = |include_billed| -> Vec<i64> {
+      invoice_time(&conn, pid, include_billed)
+        .unwrap()
+        .timeentries
+        .iter()
+        .map(|te| te.id)
+        .collect()
+    };
+    assert_eq!(ids(false), vec![unbilled]);
+    assert_eq!(ids(true).len(), 2);
+  }
+
+  #[test]
+  fn api_token_lifecycle() {
+    let (conn, uid, _) = testdb();
+    let created = create_api_token(
+      &conn,
+      uid,
+      NewApiToken {
+        name: "script".to_string(),
+      },
+    )
+    .unwrap();
+    assert_eq!(
+      api_token_user(&conn, created.token.as_str()).unwrap(),
+      Some(uid)
+    );
+    assert!(api_tokens(&conn, uid).unwrap()[0].lastused.is_some());
+    assert_eq!(api_token_user(&conn, "not a token").unwrap(), None);
+    assert!(revoke_api_token(&conn, uid, created.apitoken.id)
+      .unwrap()
+      .is_empty());
+    assert_eq!(api_token_user(&conn, created.token.as_str()).unwrap(), None);
+  }
+
+  #[test]
+  fn tag_totals() {
+    let (conn, uid, pid) = testdb();
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let mut ste = resave(&read_time_entry(&conn, test_entry(&conn, uid, pid, start)).unwrap());
+    ste.tags = Some(vec!["design".to_string(), "meetings".to_string()]);
+    save_time_entry(&conn, uid, ste, false).unwrap().unwrap();
+    test_entry(&conn, uid, pid, start + MILLIS_PER_HOUR);
+    assert_eq!(
+      project_tags(&conn, pid).unwrap(),
+      vec!["design".to_string(), "meetings".to_string()]
+    );
+    // an entry counts toward each of its tags; untagged time is under none.
+    let totals: Vec<(Option<String>, i64)> = tag_report(&conn, pid)
+      .unwrap()
+      .tags
+      .into_iter()
+      .map(|th| (th.tag, th.duration))
+      .collect();
+    assert_eq!(
+      totals,
+      vec![
+        (None, MILLIS_PER_HOUR),
+        (Some("design".to_string()), MILLIS_PER_HOUR),
+        (Some("meetings".to_string()), MILLIS_PER_HOUR)
+      ]
+    );
+  }
+
+  #[test]
+  fn audit_time_entry() {
+    let (conn, uid, pid) = testdb();
+    let te = test_entry(&conn, uid, pid, now().unwrap() - MILLIS_PER_DAY);
+    let mut ste = resave(&read_time_entry(&conn, te).unwrap());
+    ste.description = "changed".to_string();
+    save_time_entry(&conn, uid, ste, false).unwrap().unwrap();
+    delete_time_entry(&conn, uid, te, false).unwrap();
+    // most recent first.
+    let audit = project_audit(&conn, pid).unwrap();
+    let actions: Vec<String> = audit.iter().map(|a| a.action.to_string()).collect();
+    assert_eq!(actions, vec!["Delete", "Update", "Insert"]);
+    assert!(audit.iter().all(|a| a.recordid == te && a.user == uid));
+    assert_eq!(audit[1].after.as_ref().unwrap()["description"], "changed");
+    assert_eq!(audit[1].before.as_ref().unwrap()["description"], "work");
+    assert!(audit[0].after.is_none());
+  }
+
+  #[test]
+  fn project_time_all_or_nothing() {
+    let (conn, uid, pid) = testdb();
+    let start = now().unwrap() - MILLIS_PER_DAY;
+    let good = SaveTimeEntry {
+      id: None,
+      project: pid,
+      user: uid,
+      description: "work".to_string(),
+      startdate: start,
+      enddate: start + MILLIS_PER_HOUR,
+      ignore: false,
+      tags: None,
+      task: None,
+      clear_task: false,
+      changeddate: None,
+    };
+    let mut bad = good.clone();
+    bad.startdate = start + 2 * MILLIS_PER_HOUR;
+    bad.enddate = start;
+    let spt = SaveProjectTime {
+      project: pid,
+      savetimeentries: vec![good, bad],
+      deletetimeentries: Vec::new(),
+      savepayentries: Vec::new(),
+      deletepayentries: Vec::new(),
+      saveallocations: Vec::new(),
+      deleteallocations: Vec::new(),
+      unlock: None,
+    };
+    // the second entry ends before it starts, so neither is saved.
+    assert!(save_project_time(&conn, uid, spt, false).unwrap().is_err());
+    assert!(time_entries(&conn, pid).unwrap().is_empty());
+  }
+
+  #[test]
+  fn save_read_invoice() {
+    let (conn, uid, pid) = testdb();
+    let mut pi = PrintInvoice {
+      id: "INV-1".to_string(),
+      payer: "payer".to_string(),
+      payee: "payee".to_string(),
+      items: vec![InvoiceItem {
+        description: "work".to_string(),
+        duration: 2.0,
+        rate: 10.0,
+      }],
+      date: "2024-01-01".to_string(),
+      due_date: Some("2024-02-01".to_string()),
+      extra_fields: vec![ExtraField {
+        n: "po".to_string(),
+        v: "123".to_string(),
+      }],
+      currency: "$".to_string(),
+      project: Some(pid),
+      timeentries: None,
+    };
+    let id = save_invoice(&conn, uid, pid, &pi).unwrap();
+    let inv = read_invoice(&conn, id).unwrap().unwrap();
+    assert_eq!(inv.project, pid);
+    assert_eq!(inv.number, "INV-1");
+    assert_eq!(inv.total, 20.0);
+    assert_eq!(inv.creator, uid);
+    assert_eq!(inv.status, InvoiceStatus::Draft);
+    assert_eq!(inv.extra_fields.len(), 1);
+
+    // saving the same number again updates the draft.
+    pi.payer = "other".to_string();
+    assert_eq!(save_invoice(&conn, uid, pid, &pi).unwrap(), id);
+    let invs = invoices(&conn, pid).unwrap();
+    assert_eq!(invs.len(), 1);
+    assert_eq!(invs[0].payer, "other");
+
+    // unreadable items are an error, not an empty invoice.
+    conn
+      .execute(
+        "update invoice set items = 'bad' where id = ?1",
+        params![id],
+      )
+      .unwrap();
+    assert!(read_invoice(&conn, id).is_err());
+    assert!(invoices(&conn, pid).is_err());
+  }
 }